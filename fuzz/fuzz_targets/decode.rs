@@ -0,0 +1,23 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use weekend_dns::deserialization::FromBytes;
+use weekend_dns::domain_name::DomainName;
+use weekend_dns::packet::Packet;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(packet) = Packet::from_bytes(data) {
+        let _ = packet.to_string();
+    }
+
+    // start somewhere inside the input so that compression pointers have
+    // earlier labels to point at
+    let Some((&start, rest)) = data.split_first() else {
+        return;
+    };
+    let mut cursor = start as usize % (rest.len() + 1);
+    if let Ok(name) = DomainName::from_bytes(rest, &mut cursor) {
+        assert!(cursor <= rest.len());
+        let _ = name.to_string();
+    }
+});
@@ -0,0 +1,51 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use weekend_dns::domain_name::DomainName;
+use weekend_dns::packet::Packet;
+use weekend_dns::record::{Content, Kind, Record};
+
+/// Raw RDATA of a type that holds names may contain compression pointers,
+/// which mean something else once the record has moved within the message.
+fn ambiguous(record: &Record) -> bool {
+    matches!(record.data, Content::Other(_))
+        && matches!(
+            record.kind,
+            Kind::NS
+                | Kind::MD
+                | Kind::MF
+                | Kind::CNAME
+                | Kind::SOA
+                | Kind::MB
+                | Kind::MG
+                | Kind::MR
+                | Kind::PTR
+                | Kind::MINFO
+                | Kind::MX
+                | Kind::SRV
+        )
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(packet) = Packet::from_bytes(data) else {
+        return;
+    };
+    let records = || {
+        packet
+            .answers
+            .iter()
+            .chain(packet.authorities.iter())
+            .chain(packet.additionals.iter())
+    };
+    for record in records() {
+        let name = &record.name;
+        assert_eq!(*name, DomainName::new(&name.to_string()));
+    }
+    if records().any(ambiguous) {
+        return;
+    }
+
+    let encoded = packet.to_bytes().expect("failed to encode a decoded packet");
+    let decoded = Packet::from_bytes(&encoded).expect("failed to decode our own encoding");
+    assert_eq!(packet, decoded);
+});
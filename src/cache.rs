@@ -0,0 +1,434 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::process;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::deserialization::{pop_u16, pop_u64, pop_u8, FromBytes};
+use crate::domain_name::DomainName;
+use crate::error::DnsError;
+use crate::packet::Rcode;
+use crate::record::{Class, Content, Kind, Record};
+use crate::serialization::{push_u16, push_u64};
+
+/// how many record sets a cache holds unless told otherwise
+pub const DEFAULT_CAPACITY: usize = 4096;
+
+/// identifies cache files, followed by the version of the format
+const FILE_MAGIC: &[u8] = b"WDNSCACHE";
+const FILE_VERSION: u8 = 1;
+/// the longest a set can stay cached, as TTLs only have 31 bits (RFC 2181 §8)
+const MAX_TTL: Duration = Duration::from_secs(i32::MAX as u64);
+
+/// a record set, or all sets of a name when the kind is None
+type Key = (DomainName, Option<Kind>, Class);
+
+/// A record cache that can be shared between threads.
+///
+/// Records are stored per (name, kind, class) set and handed out until the
+/// lowest TTL in the set runs out. Answers saying that a name or set does not
+/// exist are kept as well (RFC 2308). Once more than `capacity` entries are
+/// held, the least recently used ones are dropped.
+#[derive(Debug)]
+pub struct Cache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    sets: HashMap<Key, Entry>,
+    /// the keys of `sets` by the tick they were last used at
+    recent: BTreeMap<u64, Key>,
+    tick: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    data: Data,
+    expires: Instant,
+    used: u64,
+}
+
+#[derive(Debug)]
+enum Data {
+    Records(Vec<Record>),
+    /// the SOA record of the zone that said there are none
+    Missing(Record),
+}
+
+/// A cached answer saying that records do not exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Negative {
+    /// NXDOMAIN if the name does not exist at all, NOERROR if it merely has
+    /// no records of the kind asked for
+    pub rcode: Rcode,
+    /// the SOA record from the authority section of the answer, its TTL
+    /// counting down like those of cached records
+    pub soa: Record,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Cache::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl Cache {
+    pub fn new(capacity: usize) -> Cache {
+        Cache {
+            capacity,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Stores `records`, replacing whatever was cached for the same sets.
+    /// Sets with a TTL of zero are not kept.
+    pub fn insert(&self, records: &[Record]) {
+        let mut sets: HashMap<Key, Vec<Record>> = HashMap::new();
+        for record in records {
+            sets.entry((record.name.clone(), Some(record.kind), record.class))
+                .or_default()
+                .push(record.clone());
+        }
+
+        let now = Instant::now();
+        let mut entries = self.lock();
+        for (key, records) in sets {
+            let ttl = records.iter().map(|r| r.ttl.max(0)).min().unwrap_or(0);
+            if ttl == 0 {
+                continue;
+            }
+            let expires = now + Duration::from_secs(ttl as u64);
+            // the name exists after all
+            entries.remove(&(key.0.clone(), None, key.2));
+            entries.put(key, Data::Records(records), expires);
+        }
+        entries.evict(self.capacity);
+    }
+
+    /// Remembers that `name` does not exist (NXDOMAIN), for as long as `soa`
+    /// allows negative answers from its zone to be kept.
+    pub fn insert_nxdomain(&self, name: &DomainName, class: Class, soa: &Record) {
+        self.insert_missing((name.clone(), None, class), soa);
+    }
+
+    /// Remembers that `name` has no records of `kind` (NODATA), for as long
+    /// as `soa` allows negative answers from its zone to be kept.
+    pub fn insert_nodata(&self, name: &DomainName, kind: Kind, class: Class, soa: &Record) {
+        self.insert_missing((name.clone(), Some(kind), class), soa);
+    }
+
+    fn insert_missing(&self, key: Key, soa: &Record) {
+        let Content::Soa(content) = &soa.data else {
+            return;
+        };
+        // RFC 2308 section 5: the lower of the SOA's own TTL and its minimum
+        let ttl = (soa.ttl.max(0) as u32).min(content.minimum);
+        if ttl == 0 {
+            return;
+        }
+        let expires = Instant::now() + Duration::from_secs(ttl as u64);
+        let mut entries = self.lock();
+        entries.put(key, Data::Missing(soa.clone()), expires);
+        entries.evict(self.capacity);
+    }
+
+    /// The records cached for `name`, `kind` and `class`, with their TTLs
+    /// counting down from the time they were stored.
+    pub fn get(&self, name: &DomainName, kind: Kind, class: Class) -> Option<Vec<Record>> {
+        match self.lookup(&(name.clone(), Some(kind), class))? {
+            Data::Records(records) => Some(records),
+            Data::Missing(_) => None,
+        }
+    }
+
+    /// Whether `name` is known not to exist or not to have records of
+    /// `kind`.
+    pub fn get_negative(&self, name: &DomainName, kind: Kind, class: Class) -> Option<Negative> {
+        if let Some(Data::Missing(soa)) = self.lookup(&(name.clone(), None, class)) {
+            return Some(Negative {
+                rcode: Rcode::NXDomain,
+                soa,
+            });
+        }
+        match self.lookup(&(name.clone(), Some(kind), class))? {
+            Data::Missing(soa) => Some(Negative {
+                rcode: Rcode::NoError,
+                soa,
+            }),
+            Data::Records(_) => None,
+        }
+    }
+
+    /// A copy of the live entry for `key`, with its records' TTLs set to the
+    /// time left.
+    fn lookup(&self, key: &Key) -> Option<Data> {
+        let now = Instant::now();
+        let mut entries = self.lock();
+        let entry = entries.sets.get(key)?;
+        if entry.expires <= now {
+            entries.remove(key);
+            return None;
+        }
+        let remaining = (entry.expires - now).as_secs() as i32;
+        let with_ttl = |r: &Record| Record {
+            ttl: remaining,
+            ..r.clone()
+        };
+        let data = match &entry.data {
+            Data::Records(records) => Data::Records(records.iter().map(with_ttl).collect()),
+            Data::Missing(soa) => Data::Missing(with_ttl(soa)),
+        };
+        entries.touch(key);
+        Some(data)
+    }
+
+    /// The closest enclosing zone of `name` whose nameservers are cached
+    /// along with at least one of their addresses.
+    pub fn delegation(&self, name: &DomainName) -> Option<(DomainName, Vec<IpAddr>)> {
+        let mut zone = Some(name.clone());
+        while let Some(current) = zone {
+            zone = current.parent();
+            let Some(nameservers) = self.get(&current, Kind::NS, Class::Internet) else {
+                continue;
+            };
+            let addresses: Vec<IpAddr> = nameservers
+                .iter()
+                .filter_map(|r| match &r.data {
+                    Content::DomainName(ns) => Some(ns),
+                    _ => None,
+                })
+                .flat_map(|ns| self.addresses(ns))
+                .collect();
+            if !addresses.is_empty() {
+                return Some((current, addresses));
+            }
+        }
+        None
+    }
+
+    /// The cached addresses of `name`, IPv4 first.
+    pub fn addresses(&self, name: &DomainName) -> Vec<IpAddr> {
+        [Kind::A, Kind::AAAA]
+            .into_iter()
+            .filter_map(|kind| self.get(name, kind, Class::Internet))
+            .flatten()
+            .filter_map(|r| match r.data {
+                Content::IPv4(ip) => Some(IpAddr::V4(ip)),
+                Content::IPv6(ip) => Some(IpAddr::V6(ip)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// the number of entries held, expired ones included
+    pub fn len(&self) -> usize {
+        self.lock().sets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        *self.lock() = Entries::default();
+    }
+
+    /// Writes the live entries to `path`, with their expiry as wall clock
+    /// time so that they can be loaded by a later process.
+    ///
+    /// Records are stored in wire format, least recently used first:
+    ///
+    /// ```text
+    /// "WDNSCACHE" version
+    /// { name has-kind kind class expires(unix seconds) negative count records }*
+    /// ```
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let now = Instant::now();
+        let wall_clock = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let mut buf = FILE_MAGIC.to_vec();
+        buf.push(FILE_VERSION);
+        {
+            let entries = self.lock();
+            for key in entries.recent.values() {
+                let Some(entry) = entries.sets.get(key).filter(|e| e.expires > now) else {
+                    continue;
+                };
+                let expires = wall_clock + (entry.expires - now);
+                // sets that were put in by hand may hold names too long to
+                // encode; those are left out
+                let start = buf.len();
+                if write_entry(&mut buf, key, &entry.data, expires).is_err() {
+                    buf.truncate(start);
+                }
+            }
+        }
+
+        // write a copy first so that a crash never leaves half a file behind,
+        // named after the process so that concurrent saves do not mix
+        let path = path.as_ref();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(format!(".{}.partial", process::id()));
+        fs::write(&partial, buf)?;
+        fs::rename(partial, path)
+    }
+
+    /// Adds the entries saved in `path` to the cache, leaving out those that
+    /// have expired since, and returns how many were added.
+    pub fn load(&self, path: impl AsRef<Path>) -> io::Result<usize> {
+        let buf = fs::read(path)?;
+        let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, reason.to_string());
+        let header_len = FILE_MAGIC.len() + 1;
+        if buf.len() < header_len || !buf.starts_with(FILE_MAGIC) {
+            return Err(invalid("not a cache file"));
+        }
+        if buf[FILE_MAGIC.len()] != FILE_VERSION {
+            return Err(invalid("unsupported cache file version"));
+        }
+
+        let mut cursor = header_len;
+        let mut saved = Vec::new();
+        while cursor < buf.len() {
+            let entry = read_entry(&buf, &mut cursor)
+                .map_err(|err| invalid(&format!("corrupt cache file: {err}")))?;
+            saved.extend(entry);
+        }
+
+        let now = Instant::now();
+        let wall_clock = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut entries = self.lock();
+        let mut count = 0;
+        for (key, data, expires) in saved {
+            let Some(remaining) = expires.checked_sub(wall_clock).filter(|d| !d.is_zero()) else {
+                continue;
+            };
+            // a file from a broken or hostile writer may claim any expiry
+            let Some(expires) = now.checked_add(remaining.min(MAX_TTL)) else {
+                continue;
+            };
+            entries.put(key, data, expires);
+            count += 1;
+        }
+        entries.evict(self.capacity);
+        Ok(count)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        // the entries are consistent between statements, so a panic in
+        // another thread leaves nothing half done
+        self.entries.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Appends one entry of a cache file, with its expiry since the Unix epoch.
+fn write_entry(
+    buf: &mut Vec<u8>,
+    key: &Key,
+    data: &Data,
+    expires: Duration,
+) -> Result<(), DnsError> {
+    let (name, kind, class) = key;
+    buf.extend_from_slice(&name.to_bytes()?);
+    buf.push(kind.is_some() as u8);
+    push_u16(buf, kind.unwrap_or_default().into());
+    push_u16(buf, (*class).into());
+    push_u64(buf, expires.as_secs());
+    let records = match data {
+        Data::Records(records) => {
+            buf.push(0);
+            records.as_slice()
+        }
+        Data::Missing(soa) => {
+            buf.push(1);
+            std::slice::from_ref(soa)
+        }
+    };
+    push_u16(buf, records.len() as u16);
+    for record in records {
+        buf.extend_from_slice(&record.to_bytes()?);
+    }
+    Ok(())
+}
+
+/// Reads one entry of a cache file, with its expiry since the Unix epoch.
+/// Negative entries without exactly one SOA record come back as None.
+fn read_entry(buf: &[u8], cursor: &mut usize) -> Result<Option<(Key, Data, Duration)>, DnsError> {
+    let name = DomainName::from_bytes(buf, cursor)?;
+    let has_kind = pop_u8(buf, cursor)? != 0;
+    let kind = Kind::from(pop_u16(buf, cursor)?);
+    let class = Class::from(pop_u16(buf, cursor)?);
+    let expires = Duration::from_secs(pop_u64(buf, cursor)?);
+    let negative = pop_u8(buf, cursor)? != 0;
+    let count = pop_u16(buf, cursor)?;
+    let mut records = (0..count)
+        .map(|_| Record::from_bytes(buf, cursor))
+        .collect::<Result<Vec<Record>, DnsError>>()?;
+    let data = match negative {
+        true if records.len() == 1 => Data::Missing(records.remove(0)),
+        true => return Ok(None),
+        false => Data::Records(records),
+    };
+    Ok(Some((
+        (name, has_kind.then_some(kind), class),
+        data,
+        expires,
+    )))
+}
+
+impl Entries {
+    fn put(&mut self, key: Key, data: Data, expires: Instant) {
+        self.remove(&key);
+        self.tick += 1;
+        self.recent.insert(self.tick, key.clone());
+        self.sets.insert(
+            key,
+            Entry {
+                data,
+                expires,
+                used: self.tick,
+            },
+        );
+    }
+
+    fn touch(&mut self, key: &Key) {
+        self.tick += 1;
+        if let Some(entry) = self.sets.get_mut(key) {
+            self.recent.remove(&entry.used);
+            entry.used = self.tick;
+            self.recent.insert(self.tick, key.clone());
+        }
+    }
+
+    fn remove(&mut self, key: &Key) {
+        if let Some(entry) = self.sets.remove(key) {
+            self.recent.remove(&entry.used);
+        }
+    }
+
+    /// Drops expired sets, then the least recently used ones until at most
+    /// `capacity` are left.
+    fn evict(&mut self, capacity: usize) {
+        if self.sets.len() <= capacity {
+            return;
+        }
+        let now = Instant::now();
+        self.sets.retain(|_, entry| entry.expires > now);
+        let sets = &self.sets;
+        self.recent.retain(|_, key| sets.contains_key(key));
+        while self.sets.len() > capacity {
+            let Some((_, key)) = self.recent.pop_first() else {
+                break;
+            };
+            self.sets.remove(&key);
+        }
+    }
+}
@@ -1,95 +1,94 @@
 use std::net::{Ipv4Addr, Ipv6Addr};
 
-pub fn pop_u16(buf: &[u8], cursor: &mut usize) -> Option<u16> {
-    let hi = *buf.get(*cursor)? as u16;
-    *cursor += 1;
-    let lo = *buf.get(*cursor)? as u16;
-    *cursor += 1;
-    Some((hi << 8) | lo)
+use crate::error::DnsError;
+
+pub fn pop_u16(buf: &[u8], cursor: &mut usize) -> Result<u16, DnsError> {
+    let hi = pop_u8(buf, cursor)? as u16;
+    let lo = pop_u8(buf, cursor)? as u16;
+    Ok((hi << 8) | lo)
+}
+
+pub fn pop_u32(buf: &[u8], cursor: &mut usize) -> Result<u32, DnsError> {
+    let hi = pop_u16(buf, cursor)? as u32;
+    let lo = pop_u16(buf, cursor)? as u32;
+    Ok((hi << 16) | lo)
 }
 
-pub fn pop_u8(buf: &[u8], cursor: &mut usize) -> Option<u8> {
-    let lo = *buf.get(*cursor)?;
+pub fn pop_u64(buf: &[u8], cursor: &mut usize) -> Result<u64, DnsError> {
+    let hi = pop_u32(buf, cursor)? as u64;
+    let lo = pop_u32(buf, cursor)? as u64;
+    Ok((hi << 32) | lo)
+}
+
+pub fn pop_u8(buf: &[u8], cursor: &mut usize) -> Result<u8, DnsError> {
+    let lo = *buf
+        .get(*cursor)
+        .ok_or(DnsError::Truncated { offset: *cursor })?;
     *cursor += 1;
-    Some(lo)
+    Ok(lo)
 }
 
 pub fn pop_collection<T: FromBytes + Sized>(
     buf: &[u8],
     cursor: &mut usize,
     count: usize,
-) -> Option<Vec<T>> {
+) -> Result<Vec<T>, DnsError> {
     let mut c = *cursor;
-    let vec: Vec<T> = (0..count)
-        .map_while(|index| -> Option<T> {
-            let Some(item) = <T as FromBytes>::from_bytes(buf, &mut c) else {
-                println!("failed to extract element {}", index);
-                return None};
-            Some(item)
-        })
-        .collect();
+    let vec = (0..count)
+        .map(|_| <T as FromBytes>::from_bytes(buf, &mut c))
+        .collect::<Result<Vec<T>, DnsError>>()?;
     *cursor = c;
-    Some(vec)
+    Ok(vec)
 }
 
 pub trait FromBytes
 where
     Self: Sized,
 {
-    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Option<Self>;
+    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Result<Self, DnsError>;
 }
 
 impl FromBytes for u8 {
-    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Option<Self> {
-        let byte = *buf.get(*cursor)?;
-        *cursor += 1;
-        Some(byte)
+    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Result<Self, DnsError> {
+        pop_u8(buf, cursor)
     }
 }
 
 impl FromBytes for char {
-    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Option<Self> {
-        let byte = *buf.get(*cursor)?;
-        let char = byte.try_into().ok()?;
-        *cursor += 1;
-        Some(char)
+    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Result<Self, DnsError> {
+        let byte = pop_u8(buf, cursor)?;
+        Ok(byte.into())
     }
 }
 
 impl FromBytes for i32 {
-    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Option<Self> {
-        let a = *buf.get(*cursor)? as u32;
-        *cursor += 1;
-        let b = *buf.get(*cursor)? as u32;
-        *cursor += 1;
-        let c = *buf.get(*cursor)? as u32;
-        *cursor += 1;
-        let d = *buf.get(*cursor)? as u32;
-        *cursor += 1;
+    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Result<Self, DnsError> {
+        let mut cur = *cursor;
+        let a = pop_u8(buf, &mut cur)? as u32;
+        let b = pop_u8(buf, &mut cur)? as u32;
+        let c = pop_u8(buf, &mut cur)? as u32;
+        let d = pop_u8(buf, &mut cur)? as u32;
+        *cursor = cur;
 
         let num = a << 24 | b << 16 | c << 8 | d;
-        Some(num as i32)
+        Ok(num as i32)
     }
 }
 
 impl FromBytes for Ipv4Addr {
-    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Option<Self> {
+    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Result<Self, DnsError> {
         let mut cur = *cursor;
-        let a = *buf.get(cur)?;
-        cur += 1;
-        let b = *buf.get(cur)?;
-        cur += 1;
-        let c = *buf.get(cur)?;
-        cur += 1;
-        let d = *buf.get(cur)?;
-        cur += 1;
+        let a = pop_u8(buf, &mut cur)?;
+        let b = pop_u8(buf, &mut cur)?;
+        let c = pop_u8(buf, &mut cur)?;
+        let d = pop_u8(buf, &mut cur)?;
         *cursor = cur;
-        Some(Ipv4Addr::new(a, b, c, d))
+        Ok(Ipv4Addr::new(a, b, c, d))
     }
 }
 
 impl FromBytes for Ipv6Addr {
-    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Option<Self> {
+    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Result<Self, DnsError> {
         let mut cur = *cursor;
         let a = pop_u16(buf, &mut cur)?;
         let b = pop_u16(buf, &mut cur)?;
@@ -100,6 +99,6 @@ impl FromBytes for Ipv6Addr {
         let g = pop_u16(buf, &mut cur)?;
         let h = pop_u16(buf, &mut cur)?;
         *cursor = cur;
-        Some(Ipv6Addr::new(a, b, c, d, e, f, g, h))
+        Ok(Ipv6Addr::new(a, b, c, d, e, f, g, h))
     }
 }
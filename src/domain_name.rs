@@ -1,74 +1,227 @@
 use std::fmt::Display;
+use std::hash::{Hash, Hasher};
+
+use rand::Rng;
 
 use crate::deserialization::{pop_collection, pop_u8, FromBytes};
+use crate::error::DnsError;
+use crate::serialization::{push_u16, NameTable};
+
+/// the longest name allowed on the wire, length bytes included
+const MAX_NAME_LEN: usize = 255;
+/// the longest label, since the top two bits of a length byte are flags
+const MAX_LABEL_LEN: usize = 63;
+
+/// the byte written as three decimal digits, if they are digits and at most 255
+fn decimal(digits: &[u8]) -> Option<u8> {
+    if !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let value = digits
+        .iter()
+        .fold(0u16, |value, digit| value * 10 + (digit - b'0') as u16);
+    u8::try_from(value).ok()
+}
 
+/// A domain name as a list of labels.
+///
+/// Labels are kept as raw bytes since nothing stops a server from sending
+/// dots, backslashes or non-ASCII bytes inside them; `Display` escapes those
+/// the way zone files do and `new` understands the same escapes.
 #[derive(Debug, Clone, Default)]
 pub struct DomainName {
-    inner: String,
+    labels: Vec<Vec<u8>>,
 }
 
 impl DomainName {
     pub fn new(name: &str) -> DomainName {
-        DomainName {
-            inner: name.to_string(),
+        let bytes = name.as_bytes();
+        let mut labels = Vec::new();
+        let mut label = Vec::new();
+        let mut index = 0;
+        while let Some(&byte) = bytes.get(index) {
+            index += 1;
+            // a \DDD escape over 255 is not a byte, so it is read as the
+            // escaped digit followed by plain text instead
+            match (byte, bytes.get(index..index + 3).and_then(decimal)) {
+                (b'.', _) => labels.push(std::mem::take(&mut label)),
+                (b'\\', Some(value)) => {
+                    label.push(value);
+                    index += 3;
+                }
+                (b'\\', _) if index < bytes.len() => {
+                    label.push(bytes[index]);
+                    index += 1;
+                }
+                _ => label.push(byte),
+            }
         }
+        labels.push(label);
+        labels.retain(|label| !label.is_empty());
+        DomainName { labels }
     }
     pub fn empty() -> DomainName {
         DomainName::new("")
     }
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(self.inner.len());
-        let parts = self.inner.split('.');
-        for part in parts {
-            let len = part.len();
-            buf.push(len as u8);
-            buf.extend_from_slice(part.as_bytes());
+    /// the labels of the name, without the empty root label
+    pub fn labels(&self) -> impl DoubleEndedIterator<Item = &[u8]> {
+        self.labels.iter().map(|label| label.as_slice())
+    }
+    /// `self` with the labels of `suffix` appended
+    pub fn join(&self, suffix: &DomainName) -> DomainName {
+        let mut labels = self.labels.clone();
+        labels.extend(suffix.labels.iter().cloned());
+        DomainName { labels }
+    }
+    /// the name with its first label removed, or None for the root
+    pub fn parent(&self) -> Option<DomainName> {
+        let (_, labels) = self.labels.split_first()?;
+        Some(DomainName {
+            labels: labels.to_vec(),
+        })
+    }
+    /// true if `self` is equal to `zone` or lies below it
+    pub fn is_subdomain_of(&self, zone: &DomainName) -> bool {
+        let mut own = self.labels().rev();
+        for label in zone.labels().rev() {
+            match own.next() {
+                Some(part) if part.eq_ignore_ascii_case(label) => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+    /// true if both names have the same labels, letter case included
+    pub fn eq_exact(&self, other: &DomainName) -> bool {
+        self.labels == other.labels
+    }
+    /// the same name with the case of every letter picked at random, for
+    /// DNS 0x20 queries
+    pub fn with_random_case(&self) -> DomainName {
+        let mut rng = rand::thread_rng();
+        let labels = self
+            .labels
+            .iter()
+            .map(|label| {
+                label
+                    .iter()
+                    .map(|byte| match rng.gen() {
+                        true => byte.to_ascii_uppercase(),
+                        false => byte.to_ascii_lowercase(),
+                    })
+                    .collect()
+            })
+            .collect();
+        DomainName { labels }
+    }
+    pub fn to_bytes(&self) -> Result<Vec<u8>, DnsError> {
+        let mut buf = Vec::new();
+        self.write(&mut buf, &mut NameTable::uncompressed())?;
+        Ok(buf)
+    }
+    /// Appends the name to a message in `buf`, pointing at the longest
+    /// suffix that `names` has seen before.
+    ///
+    /// Fails without writing anything if a label is longer than 63 bytes or
+    /// the whole name longer than 255.
+    pub fn write(&self, buf: &mut Vec<u8>, names: &mut NameTable) -> Result<(), DnsError> {
+        let wire_len: usize = self.labels().map(|part| part.len() + 1).sum::<usize>() + 1;
+        if wire_len > MAX_NAME_LEN || self.labels().any(|part| part.len() > MAX_LABEL_LEN) {
+            return Err(DnsError::BadName { name: self.clone() });
+        }
+        for (index, part) in self.labels.iter().enumerate() {
+            let suffix = &self.labels[index..];
+            if let Some(offset) = names.find(suffix) {
+                push_u16(buf, 0b11000000_00000000 | offset);
+                return Ok(());
+            }
+            names.insert(suffix, buf.len());
+            buf.push(part.len() as u8);
+            buf.extend_from_slice(part);
         }
         buf.push(0);
-        buf
+        Ok(())
+    }
+}
+
+impl PartialEq for DomainName {
+    fn eq(&self, other: &Self) -> bool {
+        self.labels.len() == other.labels.len()
+            && self
+                .labels()
+                .zip(other.labels())
+                .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+}
+
+impl Eq for DomainName {}
+
+impl Hash for DomainName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for part in self.labels() {
+            part.to_ascii_lowercase().hash(state);
+        }
     }
 }
 
 impl Display for DomainName {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        <String as Display>::fmt(&self.inner, f)
+        if self.labels.is_empty() {
+            return write!(f, ".");
+        }
+        for (index, part) in self.labels().enumerate() {
+            if index > 0 {
+                write!(f, ".")?;
+            }
+            for byte in part.iter() {
+                match byte {
+                    b'.' | b'\\' => write!(f, "\\{}", *byte as char)?,
+                    0x21..=0x7e => write!(f, "{}", *byte as char)?,
+                    _ => write!(f, "\\{byte:03}")?,
+                }
+            }
+        }
+        Ok(())
     }
 }
 
 impl FromBytes for DomainName {
-    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Option<Self> {
-        let max_cursor: usize = *cursor;
-        let mut parts = Vec::new();
+    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Result<Self, DnsError> {
+        let start = *cursor;
+        let mut labels = Vec::new();
+        let mut wire_len = 1;
+        // where reading continues, which stays put once a pointer is followed
+        let mut end = None;
+        let mut cur = *cursor;
+        // pointers must point strictly before the label sequence that
+        // contains them, so following them always terminates
+        let mut max_cursor = *cursor;
         loop {
-            let len = pop_u8(buf, cursor)? as u16;
+            let offset = cur;
+            let len = pop_u8(buf, &mut cur)?;
             if len == 0 {
                 break;
             } else if (len & 0b11000000) == 0b11000000 {
-                let lo = pop_u8(buf, cursor)? as u16;
-                let hi = (len & 0b00111111) << 8;
-                let mut pointer = (hi | lo) as usize;
-                if pointer < max_cursor {
-                    // recurse
-                    let DomainName { inner: ending } =
-                        <DomainName as FromBytes>::from_bytes(buf, &mut pointer)?;
-                    let mut start = parts.join(".");
-                    if start.is_empty() {
-                        start.push('.');
-                    }
-                    start.push_str(&ending);
-                    return Some(DomainName { inner: start });
-                } else {
-                    // todo: should be an error
-                    return None;
+                let lo = pop_u8(buf, &mut cur)? as u16;
+                let hi = (len as u16 & 0b00111111) << 8;
+                let pointer = (hi | lo) as usize;
+                if pointer >= max_cursor {
+                    return Err(DnsError::ForwardPointer { offset, pointer });
                 }
+                end.get_or_insert(cur);
+                max_cursor = pointer;
+                cur = pointer;
+                continue;
+            } else if (len & 0b11000000) != 0 {
+                return Err(DnsError::BadLabelLength { offset, len });
+            }
+            wire_len += len as usize + 1;
+            if wire_len > MAX_NAME_LEN {
+                return Err(DnsError::NameTooLong { offset: start });
             }
-            let string: String = pop_collection::<char>(buf, cursor, len as usize)?
-                .iter()
-                .collect();
-            parts.push(string);
+            labels.push(pop_collection::<u8>(buf, &mut cur, len as usize)?);
         }
-        Some(DomainName {
-            inner: parts.join("."),
-        })
+        *cursor = end.unwrap_or(cur);
+        Ok(DomainName { labels })
     }
 }
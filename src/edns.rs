@@ -0,0 +1,273 @@
+use std::fmt::Display;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::deserialization::{pop_collection, pop_u16, pop_u8};
+use crate::domain_name::DomainName;
+use crate::error::DnsError;
+use crate::record::{Class, Content, Kind, Record};
+use crate::serialization::push_u16;
+
+/// the payload size recommended by DNS flag day 2020, which avoids IP
+/// fragmentation on practically every path
+pub const DEFAULT_PAYLOAD_SIZE: u16 = 1232;
+
+const DO: u32 = 1 << 15;
+
+/// The EDNS(0) parameters of a message (RFC 6891), carried on the wire as an
+/// OPT pseudo-record in the additional section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edns {
+    /// the largest UDP payload the sender can receive
+    pub payload_size: u16,
+    /// the upper eight bits of the 12 bit response code
+    pub extended_rcode: u8,
+    pub version: u8,
+    /// DO: the sender wants DNSSEC records
+    pub dnssec_ok: bool,
+    /// the remaining flag bits, which must be zero for now
+    pub z: u16,
+    pub options: Vec<EdnsOption>,
+}
+
+impl Default for Edns {
+    fn default() -> Self {
+        Edns::new()
+    }
+}
+
+impl Edns {
+    pub fn new() -> Edns {
+        Edns {
+            payload_size: DEFAULT_PAYLOAD_SIZE,
+            extended_rcode: 0,
+            version: 0,
+            dnssec_ok: false,
+            z: 0,
+            options: vec![],
+        }
+    }
+    pub fn with_payload_size(mut self, payload_size: u16) -> Edns {
+        self.payload_size = payload_size;
+        self
+    }
+    pub fn with_dnssec_ok(mut self) -> Edns {
+        self.dnssec_ok = true;
+        self
+    }
+    pub fn with_option(mut self, option: EdnsOption) -> Edns {
+        self.options.push(option);
+        self
+    }
+
+    /// Reads the parameters out of an OPT record, if `record` is a well
+    /// formed one.
+    pub fn from_record(record: &Record) -> Option<Edns> {
+        if record.kind != Kind::OPT || record.name != DomainName::empty() {
+            return None;
+        }
+        let Content::Other(data) = &record.data else {
+            return None;
+        };
+        let mut options = Vec::new();
+        let mut cursor = 0;
+        while cursor < data.len() {
+            options.push(EdnsOption::from_bytes(data, &mut cursor).ok()?);
+        }
+        let ttl = record.ttl as u32;
+        Some(Edns {
+            payload_size: record.class.into(),
+            extended_rcode: (ttl >> 24) as u8,
+            version: (ttl >> 16) as u8,
+            dnssec_ok: ttl & DO == DO,
+            z: (ttl & (DO - 1)) as u16,
+            options,
+        })
+    }
+
+    pub fn to_record(&self) -> Record {
+        let mut data = Vec::new();
+        for option in self.options.iter() {
+            option.write(&mut data);
+        }
+        let mut ttl = (self.extended_rcode as u32) << 24 | (self.version as u32) << 16;
+        if self.dnssec_ok {
+            ttl |= DO;
+        }
+        ttl |= self.z as u32 & (DO - 1);
+        Record {
+            name: DomainName::empty(),
+            kind: Kind::OPT,
+            class: Class::from(self.payload_size),
+            ttl: ttl as i32,
+            data: Content::Other(data),
+        }
+    }
+}
+
+impl Display for Edns {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EDNS{} udp={}", self.version, self.payload_size)?;
+        if self.dnssec_ok {
+            write!(f, " do")?;
+        }
+        for option in self.options.iter() {
+            write!(f, " {option}")?;
+        }
+        Ok(())
+    }
+}
+
+/// An option in the RDATA of an OPT record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EdnsOption {
+    /// NSID (RFC 5001): empty in queries, the server's identifier in responses
+    Nsid(Vec<u8>),
+    /// ECS (RFC 7871): the network the query originates from
+    ClientSubnet {
+        source_prefix: u8,
+        scope_prefix: u8,
+        address: IpAddr,
+    },
+    /// DNS cookies (RFC 7873): the server cookie is empty in a first query
+    Cookie { client: [u8; 8], server: Vec<u8> },
+    /// padding (RFC 7830) to hide the size of encrypted messages
+    Padding(Vec<u8>),
+    Unknown { code: u16, data: Vec<u8> },
+}
+
+const NSID: u16 = 3;
+const CLIENT_SUBNET: u16 = 8;
+const COOKIE: u16 = 10;
+const PADDING: u16 = 12;
+
+impl EdnsOption {
+    pub fn code(&self) -> u16 {
+        match self {
+            EdnsOption::Nsid(_) => NSID,
+            EdnsOption::ClientSubnet { .. } => CLIENT_SUBNET,
+            EdnsOption::Cookie { .. } => COOKIE,
+            EdnsOption::Padding(_) => PADDING,
+            EdnsOption::Unknown { code, .. } => *code,
+        }
+    }
+
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        push_u16(buf, self.code());
+        let length_at = buf.len();
+        push_u16(buf, 0);
+        match self {
+            EdnsOption::Nsid(data) => buf.extend_from_slice(data),
+            EdnsOption::ClientSubnet {
+                source_prefix,
+                scope_prefix,
+                address,
+            } => {
+                let (family, octets) = match address {
+                    IpAddr::V4(ip) => (1, ip.octets().to_vec()),
+                    IpAddr::V6(ip) => (2, ip.octets().to_vec()),
+                };
+                push_u16(buf, family);
+                buf.push(*source_prefix);
+                buf.push(*scope_prefix);
+                // only as many bytes as the prefix covers
+                let len = (*source_prefix as usize).div_ceil(8).min(octets.len());
+                buf.extend_from_slice(&octets[..len]);
+            }
+            EdnsOption::Cookie { client, server } => {
+                buf.extend_from_slice(client);
+                buf.extend_from_slice(server);
+            }
+            EdnsOption::Padding(data) => buf.extend_from_slice(data),
+            EdnsOption::Unknown { data, .. } => buf.extend_from_slice(data),
+        }
+        let length = (buf.len() - length_at - 2) as u16;
+        buf[length_at..length_at + 2].copy_from_slice(&length.to_be_bytes());
+    }
+
+    /// Reads one option; options whose data does not fit their code are kept
+    /// as [`EdnsOption::Unknown`].
+    pub fn from_bytes(buf: &[u8], cursor: &mut usize) -> Result<EdnsOption, DnsError> {
+        let code = pop_u16(buf, cursor)?;
+        let len = pop_u16(buf, cursor)?;
+        let data: Vec<u8> = pop_collection(buf, cursor, len as usize)?;
+        let option = match code {
+            NSID => EdnsOption::Nsid(data),
+            CLIENT_SUBNET => client_subnet(&data).unwrap_or(EdnsOption::Unknown { code, data }),
+            // a client cookie alone, or followed by a server cookie of 8 to
+            // 32 bytes
+            COOKIE if data.len() == 8 || (16..=40).contains(&data.len()) => {
+                let mut client = [0; 8];
+                client.copy_from_slice(&data[..8]);
+                EdnsOption::Cookie {
+                    client,
+                    server: data[8..].to_vec(),
+                }
+            }
+            PADDING => EdnsOption::Padding(data),
+            _ => EdnsOption::Unknown { code, data },
+        };
+        Ok(option)
+    }
+}
+
+fn client_subnet(data: &[u8]) -> Option<EdnsOption> {
+    let mut cursor = 0;
+    let family = pop_u16(data, &mut cursor).ok()?;
+    let source_prefix = pop_u8(data, &mut cursor).ok()?;
+    let scope_prefix = pop_u8(data, &mut cursor).ok()?;
+    let octets = &data[cursor..];
+    if octets.len() != (source_prefix as usize).div_ceil(8) {
+        return None;
+    }
+    let address = match family {
+        1 if octets.len() <= 4 => {
+            let mut ip = [0; 4];
+            ip[..octets.len()].copy_from_slice(octets);
+            IpAddr::V4(Ipv4Addr::from(ip))
+        }
+        2 if octets.len() <= 16 => {
+            let mut ip = [0; 16];
+            ip[..octets.len()].copy_from_slice(octets);
+            IpAddr::V6(Ipv6Addr::from(ip))
+        }
+        _ => return None,
+    };
+    Some(EdnsOption::ClientSubnet {
+        source_prefix,
+        scope_prefix,
+        address,
+    })
+}
+
+fn hex(f: &mut std::fmt::Formatter<'_>, bytes: &[u8]) -> std::fmt::Result {
+    for byte in bytes.iter() {
+        write!(f, "{byte:02x}")?;
+    }
+    Ok(())
+}
+
+impl Display for EdnsOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EdnsOption::Nsid(data) => {
+                write!(f, "nsid=")?;
+                hex(f, data)
+            }
+            EdnsOption::ClientSubnet {
+                source_prefix,
+                scope_prefix,
+                address,
+            } => write!(f, "ecs={address}/{source_prefix}/{scope_prefix}"),
+            EdnsOption::Cookie { client, server } => {
+                write!(f, "cookie=")?;
+                hex(f, client)?;
+                hex(f, server)
+            }
+            EdnsOption::Padding(data) => write!(f, "padding={}", data.len()),
+            EdnsOption::Unknown { code, data } => {
+                write!(f, "option{code}=")?;
+                hex(f, data)
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::fmt::Display;
+use std::io;
+use std::net::IpAddr;
+
+use crate::domain_name::DomainName;
+use crate::packet::Rcode;
+
+/// Everything that can go wrong while parsing a message or resolving a name.
+///
+/// Parse errors carry the offset into the message at which decoding failed.
+#[derive(Debug)]
+pub enum DnsError {
+    /// the message ended in the middle of an item
+    Truncated { offset: usize },
+    /// a label length byte with one of the reserved 0b01/0b10 prefixes
+    BadLabelLength { offset: usize, len: u8 },
+    /// a name longer than 255 bytes on the wire
+    NameTooLong { offset: usize },
+    /// a compression pointer that does not point to an earlier label
+    ForwardPointer { offset: usize, pointer: usize },
+    /// a name with a label over 63 bytes or over 255 bytes in all, which
+    /// cannot be encoded
+    BadName { name: DomainName },
+    /// sending or receiving failed
+    Socket(io::Error),
+    /// no response arrived in time
+    Timeout,
+    /// the response did not repeat the letter case of a randomised query name
+    CaseMismatch { server: IpAddr },
+    /// the server could not process the query (SERVFAIL)
+    ServerFailure { server: IpAddr },
+    /// the name does not exist (NXDOMAIN)
+    NameError { name: DomainName },
+    /// the server answered with another non-zero response code
+    Rcode { server: IpAddr, rcode: Rcode },
+    /// a referral that does not lead closer to the name
+    LameReferral { zone: DomainName },
+    /// none of the nameservers of a zone could be reached or resolved
+    NoNameservers { zone: DomainName },
+    /// resolving the name led back to itself
+    Loop { name: DomainName },
+    /// too many referrals, nested lookups or aliases
+    LimitExceeded { name: DomainName },
+}
+
+impl Display for DnsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DnsError::Truncated { offset } => write!(f, "message truncated at byte {offset}"),
+            DnsError::BadLabelLength { offset, len } => {
+                write!(f, "bad label length {len:#04x} at byte {offset}")
+            }
+            DnsError::NameTooLong { offset } => write!(f, "name at byte {offset} is too long"),
+            DnsError::ForwardPointer { offset, pointer } => {
+                write!(f, "compression pointer at byte {offset} points forward to {pointer}")
+            }
+            DnsError::BadName { name } => write!(f, "{name} is too long to encode"),
+            DnsError::Socket(err) => write!(f, "socket error: {err}"),
+            DnsError::Timeout => write!(f, "timed out"),
+            DnsError::CaseMismatch { server } => {
+                write!(f, "{server} did not preserve the case of the query name")
+            }
+            DnsError::ServerFailure { server } => write!(f, "server failure from {server}"),
+            DnsError::NameError { name } => write!(f, "{name} does not exist"),
+            DnsError::Rcode { server, rcode } => write!(f, "{server} answered with {rcode}"),
+            DnsError::LameReferral { zone } => write!(f, "lame referral to {zone}"),
+            DnsError::NoNameservers { zone } => write!(f, "no usable nameserver for {zone}"),
+            DnsError::Loop { name } => write!(f, "resolving {name} loops"),
+            DnsError::LimitExceeded { name } => write!(f, "gave up on {name} after too many steps"),
+        }
+    }
+}
+
+impl std::error::Error for DnsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DnsError::Socket(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DnsError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => DnsError::Timeout,
+            _ => DnsError::Socket(err),
+        }
+    }
+}
@@ -1,15 +1,25 @@
-use std::net::{Ipv4Addr, Ipv6Addr, IpAddr, UdpSocket};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, OnceLock};
 
-use domain_name::DomainName;
+use cache::Cache;
+use error::DnsError;
+use lookup::Lookup;
 use record::Kind;
+use resolver::Resolver;
 
-use crate::packet::{Packet, Flags, Question};
-
+pub mod cache;
 pub mod deserialization;
 pub mod domain_name;
+pub mod edns;
+pub mod error;
+pub mod lookup;
 pub mod packet;
 pub mod record;
+pub mod resolv_conf;
+pub mod resolver;
 pub mod serialization;
+pub mod stub;
+pub mod transport;
 
 
 pub const ROOT_SERVERS: &[(&str, Ipv4Addr, &str, &str)] = &[("a.root-servers.net",Ipv4Addr::new(198,41,0,4),"2001:503:ba3e::2:30","Verisign, Inc."),
@@ -27,52 +37,20 @@ pub const ROOT_SERVERS: &[(&str, Ipv4Addr, &str, &str)] = &[("a.root-servers.net
 ("m.root-servers.net",Ipv4Addr::new(202,12,27,33),"001:dc3::35","WIDE Project")];
 
 
-pub fn resolve(domain: &str, kind: Kind) -> Option<IpAddr> {
+/// The cache shared by [`resolve`] and [`lookup`] within a process.
+pub fn shared_cache() -> Arc<Cache> {
+    static CACHE: OnceLock<Arc<Cache>> = OnceLock::new();
+    CACHE.get_or_init(|| Arc::new(Cache::default())).clone()
+}
 
-    let Ok(socket) = UdpSocket::bind("0.0.0.0:5353") else {
-        println!("failed to bind to port");
-        return None;
-    };
+/// Resolves `domain` iteratively, starting at the root servers and following
+/// NS referrals down the delegation chain until a server answers.
+pub fn resolve(domain: &str, kind: Kind) -> Result<Option<IpAddr>, DnsError> {
+    Resolver::new().with_cache(shared_cache()).resolve(domain, kind)
+}
 
-    {
-        let query = Packet::new().with_flags(Flags::new()).with_question(
-            Question::new()
-                .with_domain_name(&domain)
-                .with_kind(kind),
-        );
-        println!("Sending query: {}", query);
-        let buf = query.to_bytes();
-        let Ok(_) = socket.send_to(&buf, "198.41.0.4:53") else { // 198.41.0.4:53
-            panic!("failed to send packet");
-        };
-    }
-    {
-        let mut buf = [0u8; 1024];
-        let Ok((count,_addr)) = socket.recv_from(&mut buf) else {
-            println!("failed to receive anything");
-            return None;
-        };
-        // println!("debug packet {{");
-        // for byte in 0..count.min(1024) {
-        //     print!("{:x} ", buf[byte]);
-        // }
-        // println!("}}");
-        let Some(response) = Packet::from_bytes(&buf) else {
-            println!("failed to parse packet");
-            return None;
-        };
-        println!("Got response packet: {}", response);
-        let answer = response.answers.first().and_then(|r| {
-            match r.data {
-                record::Content::IPv4(ip) => Some(IpAddr::V4(ip)),
-                record::Content::IPv6(ip) => Some(IpAddr::V6(ip)),
-                record::Content::DomainName(_) => None,
-                record::Content::Text(_) => None,
-                record::Content::Other(_) => None,
-            }
-        });
-    answer
-    }
-    
-    
-}
\ No newline at end of file
+/// Like [`resolve`], but returns every matching record along with the CNAME
+/// chain that led to it and the details of the final answer.
+pub fn lookup(domain: &str, kind: Kind) -> Result<Lookup, DnsError> {
+    Resolver::new().with_cache(shared_cache()).lookup(domain, kind)
+}
@@ -0,0 +1,69 @@
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::domain_name::DomainName;
+use crate::packet::Rcode;
+use crate::record::{Content, Kind, Record};
+
+/// The result of resolving a name: every matching record together with how
+/// it was found.
+#[derive(Debug, Clone)]
+pub struct Lookup {
+    /// the name that was asked for
+    pub name: DomainName,
+    pub kind: Kind,
+    /// the names that turned out to be aliases, in the order they were walked
+    pub aliases: Vec<DomainName>,
+    /// the name the records actually belong to
+    pub canonical: DomainName,
+    /// the records of `kind` owned by `canonical`
+    pub records: Vec<Record>,
+    /// the server that gave the final answer, or None if it came from the
+    /// cache
+    pub server: Option<IpAddr>,
+    /// the response code of the final answer
+    pub rcode: Rcode,
+    /// the time taken by the whole lookup, referrals included
+    pub elapsed: Duration,
+}
+
+impl Lookup {
+    /// The addresses among the records, in the order the server sent them.
+    pub fn addresses(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        self.records.iter().filter_map(|r| match r.data {
+            Content::IPv4(ip) => Some(IpAddr::V4(ip)),
+            Content::IPv6(ip) => Some(IpAddr::V6(ip)),
+            _ => None,
+        })
+    }
+    /// The lowest TTL among the records, i.e. how long the whole answer may
+    /// be reused.
+    pub fn ttl(&self) -> Option<Duration> {
+        self.records
+            .iter()
+            .map(|r| Duration::from_secs(r.ttl.max(0) as u64))
+            .min()
+    }
+}
+
+impl Display for Lookup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} from ", self.name, self.kind)?;
+        match self.server {
+            Some(server) => write!(f, "{}", server)?,
+            None => write!(f, "cache")?,
+        }
+        writeln!(f, " in {:?} ({})", self.elapsed, self.rcode)?;
+        for alias in self.aliases.iter() {
+            writeln!(f, "\talias {}", alias)?;
+        }
+        if self.records.is_empty() {
+            writeln!(f, "\tno records for {}", self.canonical)?;
+        }
+        for record in self.records.iter() {
+            writeln!(f, "\t{}", record)?;
+        }
+        Ok(())
+    }
+}
@@ -1,23 +1,74 @@
 use std::env;
-use std::net::{UdpSocket, Ipv4Addr};
+use std::fs;
+use std::path::PathBuf;
 
-use weekend_dns::{packet::*, resolve};
 use weekend_dns::record::Kind;
-use weekend_dns::ROOT_SERVERS;
+use weekend_dns::resolv_conf::RESOLV_CONF;
+use weekend_dns::resolver::Resolver;
+use weekend_dns::shared_cache;
+use weekend_dns::stub::StubResolver;
 
-const ROOT_SERVER: Ipv4Addr = ROOT_SERVERS[0].1;
+/// Where the cache is kept between runs: `$WEEKEND_DNS_CACHE`, or a file
+/// under the user's cache directory.
+fn cache_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("WEEKEND_DNS_CACHE") {
+        return Some(PathBuf::from(path));
+    }
+    let dir = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(dir.join("weekend-dns").join("cache"))
+}
 
 fn main() {
-    let mut args = env::args();
-    let _ = args.next();
-    let domain_str = args.next().unwrap_or("www.google.com".to_string());
+    let (flags, mut args): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    args.reverse();
+    let domain_str = args.pop().unwrap_or("www.google.com".to_string());
 
     let record_kind: Kind = args
-        .next()
-        .and_then(|s| s.parse::<u16>().ok().and_then(|n| n.try_into().ok()))
+        .pop()
+        .and_then(|s| s.parse::<u16>().ok())
+        .map(Kind::from)
         .unwrap_or(Kind::A);
 
+    let cache = shared_cache();
+    let cache_path = cache_path();
+    if let Some(path) = cache_path.as_ref().filter(|path| path.exists()) {
+        if let Err(err) = cache.load(path) {
+            println!("ignoring cache at {}: {}", path.display(), err);
+        }
+    }
+
+    let mut resolver = Resolver::new().with_cache(cache.clone());
+    if flags.iter().any(|flag| flag == "--tcp") {
+        resolver = resolver.with_tcp();
+    }
+
     println!("requesting address for {}", domain_str);
-    let address = resolve(&domain_str, record_kind);
-    println!("got {:?}", address);
+    let lookup = if flags.iter().any(|flag| flag == "--stub") {
+        match StubResolver::from_system() {
+            Ok(stub) => stub.lookup(&domain_str, record_kind),
+            Err(err) => {
+                println!("could not read {}: {}", RESOLV_CONF, err);
+                return;
+            }
+        }
+    } else {
+        resolver.lookup(&domain_str, record_kind)
+    };
+    match lookup {
+        Ok(lookup) => print!("got {}", lookup),
+        Err(err) => println!("failed: {}", err),
+    }
+
+    if let Some(path) = cache_path {
+        let saved = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| cache.save(&path));
+        if let Err(err) = saved {
+            println!("could not save cache to {}: {}", path.display(), err);
+        }
+    }
 }
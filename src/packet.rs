@@ -1,16 +1,34 @@
 use rand::Rng;
 use std::fmt::Display;
-use std::net::Ipv4Addr;
 
 use crate::deserialization::{pop_collection, pop_u16, FromBytes};
 use crate::domain_name::DomainName;
+use crate::edns::Edns;
+use crate::error::DnsError;
 use crate::record::Record;
 use crate::record::{Class, Kind};
-use crate::serialization::push_u16;
+use crate::serialization::{push_u16, NameTable};
 
 
+const QR: u16 = 1 << 15;
+const AA: u16 = 1 << 10;
+const TC: u16 = 1 << 9;
+const RD: u16 = 1 << 8;
+const RA: u16 = 1 << 7;
+const Z: u16 = 1 << 6;
+const AD: u16 = 1 << 5;
+const CD: u16 = 1 << 4;
+const OPCODE_SHIFT: u16 = 11;
+const OPCODE_MASK: u16 = 0b1111 << OPCODE_SHIFT;
+const RCODE_MASK: u16 = 0b1111;
 
-#[derive(Default, Clone, Copy)]
+/// The second 16 bits of the header, laid out as in RFC 1035 section 4.1.1
+/// (plus AD and CD from RFC 4035):
+///
+/// ```text
+/// | QR | Opcode (4) | AA | TC | RD | RA | Z | AD | CD | RCODE (4) |
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct Flags(u16);
 
 impl Flags {
@@ -18,25 +36,289 @@ impl Flags {
         Flags(0)
     }
     pub fn with_recusion(mut self) -> Flags {
-        self.0 |= 1 << 8;
+        self.0 |= RD;
         self
     }
+
+    fn get(&self, bit: u16) -> bool {
+        self.0 & bit == bit
+    }
+    fn set(&mut self, bit: u16, value: bool) {
+        if value {
+            self.0 |= bit;
+        } else {
+            self.0 &= !bit;
+        }
+    }
+
+    /// QR: the message is a response rather than a query
+    pub fn is_response(&self) -> bool {
+        self.get(QR)
+    }
+    pub fn set_response(&mut self, value: bool) {
+        self.set(QR, value)
+    }
+    pub fn opcode(&self) -> Opcode {
+        Opcode::from(((self.0 & OPCODE_MASK) >> OPCODE_SHIFT) as u8)
+    }
+    pub fn set_opcode(&mut self, opcode: Opcode) {
+        self.0 = (self.0 & !OPCODE_MASK) | (((u8::from(opcode) as u16) << OPCODE_SHIFT) & OPCODE_MASK);
+    }
+    /// AA: the responding server is an authority for the name
+    pub fn is_authoritative(&self) -> bool {
+        self.get(AA)
+    }
+    pub fn set_authoritative(&mut self, value: bool) {
+        self.set(AA, value)
+    }
+    /// TC: the message was cut short to fit the transport
+    pub fn is_truncated(&self) -> bool {
+        self.get(TC)
+    }
+    pub fn set_truncated(&mut self, value: bool) {
+        self.set(TC, value)
+    }
+    /// RD: the client asks the server to resolve the name recursively
+    pub fn recursion_desired(&self) -> bool {
+        self.get(RD)
+    }
+    pub fn set_recursion_desired(&mut self, value: bool) {
+        self.set(RD, value)
+    }
+    /// RA: the server offers recursive resolution
+    pub fn recursion_available(&self) -> bool {
+        self.get(RA)
+    }
+    pub fn set_recursion_available(&mut self, value: bool) {
+        self.set(RA, value)
+    }
+    /// AD: the server has validated the data with DNSSEC
+    pub fn authentic_data(&self) -> bool {
+        self.get(AD)
+    }
+    pub fn set_authentic_data(&mut self, value: bool) {
+        self.set(AD, value)
+    }
+    /// CD: the client will do its own DNSSEC validation
+    pub fn checking_disabled(&self) -> bool {
+        self.get(CD)
+    }
+    pub fn set_checking_disabled(&mut self, value: bool) {
+        self.set(CD, value)
+    }
+    pub fn rcode(&self) -> Rcode {
+        Rcode::from(self.0 & RCODE_MASK)
+    }
+    /// Sets the response code; only the low four bits fit in the header.
+    pub fn set_rcode(&mut self, rcode: Rcode) {
+        self.0 = (self.0 & !RCODE_MASK) | (u16::from(rcode) & RCODE_MASK);
+    }
+}
+
+impl From<u16> for Flags {
+    fn from(value: u16) -> Self {
+        Flags(value)
+    }
+}
+
+impl From<Flags> for u16 {
+    fn from(flags: Flags) -> Self {
+        flags.0
+    }
+}
+
+fn flag_write(
+    f: &mut std::fmt::Formatter<'_>,
+    flags: &Flags,
+    bit: u16,
+    zero_label: &str,
+    one_label: &str,
+) -> std::fmt::Result {
+    let label = if flags.get(bit) { one_label } else { zero_label };
+    write!(f, "{label}")
+}
+
+impl Display for Flags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        flag_write(f, self, QR, "Q-", "R-")?;
+        write!(f, "{}-", self.opcode())?;
+        flag_write(f, self, AA, "aa-", "AA-")?;
+        flag_write(f, self, TC, "tc-", "TC-")?;
+        flag_write(f, self, RD, "rd-", "RD-")?;
+        flag_write(f, self, RA, "ra-", "RA-")?;
+        flag_write(f, self, Z, "z-", "Z-")?;
+        flag_write(f, self, AD, "ad-", "AD-")?;
+        flag_write(f, self, CD, "cd-", "CD-")?;
+        write!(f, "{}", self.rcode())
+    }
+}
+
+/// The kind of query, from the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    /// a standard query
+    Query,
+    /// an inverse query (obsolete)
+    IQuery,
+    /// a server status request
+    Status,
+    /// a zone change notification (RFC 1996)
+    Notify,
+    /// a dynamic update (RFC 2136)
+    Update,
+    Unknown(u8),
+}
+
+impl From<u8> for Opcode {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Opcode::Query,
+            1 => Opcode::IQuery,
+            2 => Opcode::Status,
+            4 => Opcode::Notify,
+            5 => Opcode::Update,
+            _ => Opcode::Unknown(value),
+        }
+    }
+}
+
+impl From<Opcode> for u8 {
+    fn from(opcode: Opcode) -> Self {
+        match opcode {
+            Opcode::Query => 0,
+            Opcode::IQuery => 1,
+            Opcode::Status => 2,
+            Opcode::Notify => 4,
+            Opcode::Update => 5,
+            Opcode::Unknown(value) => value,
+        }
+    }
+}
+
+impl Display for Opcode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Opcode::Query => write!(f, "QUERY"),
+            Opcode::IQuery => write!(f, "IQUERY"),
+            Opcode::Status => write!(f, "STATUS"),
+            Opcode::Notify => write!(f, "NOTIFY"),
+            Opcode::Update => write!(f, "UPDATE"),
+            Opcode::Unknown(value) => write!(f, "OPCODE{value}"),
+        }
+    }
+}
+
+/// The outcome of a query, from the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rcode {
+    NoError,
+    /// the server could not interpret the query
+    FormErr,
+    /// the server failed to process the query
+    ServFail,
+    /// the name does not exist
+    NXDomain,
+    /// the server does not support this kind of query
+    NotImp,
+    /// the server will not answer, by policy
+    Refused,
+    /// a name exists that should not (RFC 2136)
+    YXDomain,
+    /// a record set exists that should not (RFC 2136)
+    YXRRSet,
+    /// a record set that should exist does not (RFC 2136)
+    NXRRSet,
+    /// the server is not authoritative for the zone (RFC 2136)
+    NotAuth,
+    /// a name is outside of the zone (RFC 2136)
+    NotZone,
+    /// the server does not implement the requested EDNS version
+    BadVers,
+    /// the server cookie was missing or wrong (RFC 7873)
+    BadCookie,
+    Unknown(u16),
 }
 
-#[derive(Debug, Default, Clone)]
+impl From<u16> for Rcode {
+    fn from(value: u16) -> Self {
+        match value {
+            0 => Rcode::NoError,
+            1 => Rcode::FormErr,
+            2 => Rcode::ServFail,
+            3 => Rcode::NXDomain,
+            4 => Rcode::NotImp,
+            5 => Rcode::Refused,
+            6 => Rcode::YXDomain,
+            7 => Rcode::YXRRSet,
+            8 => Rcode::NXRRSet,
+            9 => Rcode::NotAuth,
+            10 => Rcode::NotZone,
+            16 => Rcode::BadVers,
+            23 => Rcode::BadCookie,
+            _ => Rcode::Unknown(value),
+        }
+    }
+}
+
+impl From<Rcode> for u16 {
+    fn from(rcode: Rcode) -> Self {
+        match rcode {
+            Rcode::NoError => 0,
+            Rcode::FormErr => 1,
+            Rcode::ServFail => 2,
+            Rcode::NXDomain => 3,
+            Rcode::NotImp => 4,
+            Rcode::Refused => 5,
+            Rcode::YXDomain => 6,
+            Rcode::YXRRSet => 7,
+            Rcode::NXRRSet => 8,
+            Rcode::NotAuth => 9,
+            Rcode::NotZone => 10,
+            Rcode::BadVers => 16,
+            Rcode::BadCookie => 23,
+            Rcode::Unknown(value) => value,
+        }
+    }
+}
+
+impl Display for Rcode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rcode::NoError => write!(f, "NOERROR"),
+            Rcode::FormErr => write!(f, "FORMERR"),
+            Rcode::ServFail => write!(f, "SERVFAIL"),
+            Rcode::NXDomain => write!(f, "NXDOMAIN"),
+            Rcode::NotImp => write!(f, "NOTIMP"),
+            Rcode::Refused => write!(f, "REFUSED"),
+            Rcode::YXDomain => write!(f, "YXDOMAIN"),
+            Rcode::YXRRSet => write!(f, "YXRRSET"),
+            Rcode::NXRRSet => write!(f, "NXRRSET"),
+            Rcode::NotAuth => write!(f, "NOTAUTH"),
+            Rcode::NotZone => write!(f, "NOTZONE"),
+            Rcode::BadVers => write!(f, "BADVERS"),
+            Rcode::BadCookie => write!(f, "BADCOOKIE"),
+            Rcode::Unknown(value) => write!(f, "RCODE{value}"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Packet {
     pub id: u16,
-    pub flags: u16,
+    pub flags: Flags,
     pub questions: Vec<Question>,
     pub answers: Vec<Record>,
     pub authorities: Vec<Record>,
+    /// the additional section, without the OPT record
     pub additionals: Vec<Record>,
+    /// the EDNS parameters, if the message has an OPT record
+    pub edns: Option<Edns>,
 }
 
 impl Packet {
     pub fn new() -> Packet {
         let id = rand::thread_rng().gen();
-        let flags = 0;
+        let flags = Flags::new();
         Packet {
             id,
             flags,
@@ -44,17 +326,53 @@ impl Packet {
             answers: vec![],
             authorities: vec![],
             additionals: vec![],
+            edns: None,
         }
     }
 
     pub fn with_flags(mut self, flags: Flags) -> Packet {
-        self.flags = flags.0;
+        self.flags = flags;
+        self
+    }
+    pub fn opcode(&self) -> Opcode {
+        self.flags.opcode()
+    }
+    pub fn set_opcode(&mut self, opcode: Opcode) {
+        self.flags.set_opcode(opcode)
+    }
+    /// The response code, including the upper bits from EDNS if present.
+    pub fn rcode(&self) -> Rcode {
+        let low = u16::from(self.flags.rcode());
+        let high = self.edns.as_ref().map_or(0, |edns| edns.extended_rcode as u16);
+        Rcode::from(high << 4 | low)
+    }
+    /// Sets the response code; codes above 15 need EDNS to be representable.
+    pub fn set_rcode(&mut self, rcode: Rcode) {
+        self.flags.set_rcode(rcode);
+        if let Some(edns) = self.edns.as_mut() {
+            edns.extended_rcode = (u16::from(rcode) >> 4) as u8;
+        }
+    }
+    pub fn with_edns(mut self, edns: Edns) -> Packet {
+        self.edns = Some(edns);
         self
     }
     pub fn with_question(mut self, question: Question) -> Packet {
         self.questions.push(question);
         self
     }
+    pub fn with_answer(mut self, record: Record) -> Packet {
+        self.answers.push(record);
+        self
+    }
+    pub fn with_authority(mut self, record: Record) -> Packet {
+        self.authorities.push(record);
+        self
+    }
+    pub fn with_additional(mut self, record: Record) -> Packet {
+        self.additionals.push(record);
+        self
+    }
     // pub fn build(domain: &str, kind: Kind) -> Packet {
     //     let q = Question::build(domain, kind);
     //     let id = rand::thread_rng().gen();
@@ -72,21 +390,51 @@ impl Packet {
         self.id = id;
         self
     }
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
-        push_u16(&mut buf, self.id);
-        push_u16(&mut buf, self.flags);
-        push_u16(&mut buf, self.questions.len() as u16);
-        push_u16(&mut buf, 0);
-        push_u16(&mut buf, 0);
-        push_u16(&mut buf, 0);
+    /// true if `self` is a response to `query`: same id and the same
+    /// questions, ignoring the letter case of the names.
+    ///
+    /// Servers may leave the question out of a FORMERR response since they
+    /// could not make sense of it.
+    pub fn is_response_to(&self, query: &Packet) -> bool {
+        let same_questions = self.questions.len() == query.questions.len()
+            && self.questions.iter().zip(&query.questions).all(|(ours, theirs)| {
+                ours.name == theirs.name
+                    && ours.kind == theirs.kind
+                    && ours.class == theirs.class
+            });
+        self.id == query.id
+            && self.flags.is_response()
+            && (same_questions || (self.questions.is_empty() && self.rcode() == Rcode::FormErr))
+    }
+    pub fn to_bytes(&self) -> Result<Vec<u8>, DnsError> {
+        let header = Header {
+            id: self.id,
+            flags: self.flags.into(),
+            questions: self.questions.len() as u16,
+            answers: self.answers.len() as u16,
+            authorities: self.authorities.len() as u16,
+            additionals: (self.additionals.len() + self.edns.is_some() as usize) as u16,
+        };
+        let mut buf = header.to_bytes();
+        let mut names = NameTable::new();
 
         for question in self.questions.iter() {
-            buf.extend_from_slice(&question.to_bytes());
+            question.write(&mut buf, &mut names)?;
         }
-        buf
+        for record in self
+            .answers
+            .iter()
+            .chain(self.authorities.iter())
+            .chain(self.additionals.iter())
+        {
+            record.write(&mut buf, &mut names)?;
+        }
+        if let Some(edns) = self.edns.as_ref() {
+            edns.to_record().write(&mut buf, &mut names)?;
+        }
+        Ok(buf)
     }
-    pub fn from_bytes(buf: &[u8]) -> Option<Packet> {
+    pub fn from_bytes(buf: &[u8]) -> Result<Packet, DnsError> {
         let mut cursor = 0;
         let header = Header::from_bytes(buf, &mut cursor)?;
 
@@ -101,65 +449,40 @@ impl Packet {
         let questions = pop_collection(buf, &mut cursor, questions as usize)?;
         let answers = pop_collection(buf, &mut cursor, answers as usize)?;
         let authorities = pop_collection(buf, &mut cursor, authorities as usize)?;
-        let additionals = pop_collection(buf, &mut cursor, additionals as usize)?;
+        let mut additionals: Vec<Record> = pop_collection(buf, &mut cursor, additionals as usize)?;
+
+        // a message may only have one OPT record, anything else is left
+        // alone as plain records
+        let mut opts = additionals.iter().enumerate().filter(|(_, r)| r.kind == Kind::OPT);
+        let edns = match (opts.next(), opts.next()) {
+            (Some((index, record)), None) => Edns::from_record(record).map(|edns| (index, edns)),
+            _ => None,
+        }
+        .map(|(index, edns)| {
+            additionals.remove(index);
+            edns
+        });
 
-        Some(Packet {
+        Ok(Packet {
             id,
-            flags,
+            flags: flags.into(),
             questions,
             answers,
             authorities,
             additionals,
+            edns,
         })
     }
 }
 
-fn flag_write(
-    f: &mut std::fmt::Formatter<'_>,
-    flags: &u16,
-    offset: usize,
-    zero_label: &str,
-    one_label: &str,
-) -> std::fmt::Result {
-    let label = if (flags & (0b1 << offset)) == (0b1 << offset) {
-        one_label
-    } else {
-        zero_label
-    };
-    write!(f, "{label}")
-}
-
 impl Display for Packet {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Packet#{:x} (", self.id)?;
-        {
-            flag_write(f, &self.flags, 0, "Q-", "R-")?;
-            // if  (self.flags & (1 << 0)) == (1 << 0) {
-            //     write!(f, "Qr ")?;
-            // } else {
-            //     write!(f, "qR ")?;
-            // }
-
-            let opcode = (self.flags >> 1) & 0b1111;
-            write!(f, "{opcode:02x}-")?;
-
-            flag_write(f, &self.flags, 5, "aa-", "AA-")?;
-            flag_write(f, &self.flags, 6, "tc-", "TC-")?;
-            flag_write(f, &self.flags, 7, "rd-", "RD-")?;
-            flag_write(f, &self.flags, 8, "ra-", "RA-")?;
-            flag_write(f, &self.flags, 9, "z-", "Z-")?;
-            flag_write(f, &self.flags, 10, "ad-", "AD-")?;
-            flag_write(f, &self.flags, 11, "cd-", "CD-")?;
-
-            let rcode = (self.flags >> 12) & 0b1111;
-            write!(f, "{rcode:02x}")?;
-
-            writeln!(f, ")")?;
-        }
+        writeln!(f, "Packet#{:x} ({})", self.id, self.flags)?;
         if self.questions.is_empty()
             && self.answers.is_empty()
             && self.authorities.is_empty()
             && self.additionals.is_empty()
+            && self.edns.is_none()
         {
             write!(f, "Empty Packet")?;
         }
@@ -187,6 +510,9 @@ impl Display for Packet {
                 writeln!(f, "\t\t{}", q)?;
             }
         }
+        if let Some(edns) = self.edns.as_ref() {
+            writeln!(f, "\t{}", edns)?;
+        }
         writeln!(f)
     }
 }
@@ -212,14 +538,14 @@ impl Header {
         push_u16(&mut buf, self.additionals);
         buf
     }
-    pub fn from_bytes(buf: &[u8], cursor: &mut usize) -> Option<Header> {
+    pub fn from_bytes(buf: &[u8], cursor: &mut usize) -> Result<Header, DnsError> {
         let id = pop_u16(buf, cursor)?;
         let flags = pop_u16(buf, cursor)?;
         let questions = pop_u16(buf, cursor)?;
         let answers = pop_u16(buf, cursor)?;
         let authorities = pop_u16(buf, cursor)?;
         let additionals = pop_u16(buf, cursor)?;
-        Some(Header {
+        Ok(Header {
             id,
             flags,
             questions,
@@ -230,7 +556,7 @@ impl Header {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Question {
     name: DomainName,
     kind: Kind,
@@ -249,10 +575,18 @@ impl Question {
         self.name = DomainName::new(name);
         self
     }
+    pub fn with_name(mut self, name: DomainName) -> Question {
+        self.name = name;
+        self
+    }
     pub fn with_kind(mut self, kind: Kind) -> Question {
         self.kind = kind;
         self
     }
+    pub fn with_class(mut self, class: Class) -> Question {
+        self.class = class;
+        self
+    }
     pub fn build(name: &str, kind: Kind) -> Question {
         let name = DomainName::new(name);
         Question {
@@ -261,11 +595,21 @@ impl Question {
             class: Class::Internet,
         }
     }
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut buf: Vec<u8> = self.name.to_bytes();
-        push_u16(&mut buf, self.kind as u16);
-        push_u16(&mut buf, self.class as u16);
-        buf
+    pub fn name(&self) -> &DomainName {
+        &self.name
+    }
+    pub fn to_bytes(&self) -> Result<Vec<u8>, DnsError> {
+        let mut buf = Vec::new();
+        self.write(&mut buf, &mut NameTable::uncompressed())?;
+        Ok(buf)
+    }
+    /// Appends the question to a message in `buf`, compressing its name via
+    /// `names`.
+    pub fn write(&self, buf: &mut Vec<u8>, names: &mut NameTable) -> Result<(), DnsError> {
+        self.name.write(buf, names)?;
+        push_u16(buf, self.kind.into());
+        push_u16(buf, self.class.into());
+        Ok(())
     }
 }
 
@@ -281,10 +625,10 @@ impl Display for Question {
 }
 
 impl FromBytes for Question {
-    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Option<Self> {
+    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Result<Self, DnsError> {
         let name = DomainName::from_bytes(buf, cursor)?;
         let kind = Kind::from_bytes(buf, cursor)?;
         let class = Class::from_bytes(buf, cursor)?;
-        Some(Question { name, kind, class })
+        Ok(Question { name, kind, class })
     }
 }
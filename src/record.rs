@@ -4,11 +4,13 @@ use std::{
 };
 
 use crate::{
-    deserialization::{pop_collection, pop_u16, FromBytes},
+    deserialization::{pop_collection, pop_u16, pop_u32, pop_u8, FromBytes},
     domain_name::DomainName,
+    error::DnsError,
+    serialization::{push_u16, push_u32, NameTable},
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Record {
     pub name: DomainName,
     pub kind: Kind,
@@ -29,14 +31,315 @@ impl Display for Record {
     }
 }
 
+impl Record {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, DnsError> {
+        let mut buf = Vec::new();
+        self.write(&mut buf, &mut NameTable::uncompressed())?;
+        Ok(buf)
+    }
+    /// Appends the record to a message in `buf`, compressing names via `names`.
+    pub fn write(&self, buf: &mut Vec<u8>, names: &mut NameTable) -> Result<(), DnsError> {
+        self.name.write(buf, names)?;
+        push_u16(buf, self.kind.into());
+        push_u16(buf, self.class.into());
+        push_u32(buf, self.ttl as u32);
+        let length_at = buf.len();
+        push_u16(buf, 0);
+        self.data.write(buf, names)?;
+        let length = (buf.len() - length_at - 2) as u16;
+        buf[length_at..length_at + 2].copy_from_slice(&length.to_be_bytes());
+        Ok(())
+    }
+}
+
 impl FromBytes for Record {
-    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Option<Self> {
+    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Result<Self, DnsError> {
         let name = DomainName::from_bytes(buf, cursor)?;
         let kind = Kind::from_bytes(buf, cursor)?;
         let class = Class::from_bytes(buf, cursor)?;
         let ttl = i32::from_bytes(buf, cursor)?;
         let count = pop_u16(buf, cursor)?;
-        let expected = *cursor + count as usize;
+        let start = *cursor;
+        let end = start + count as usize;
+        if end > buf.len() {
+            return Err(DnsError::Truncated { offset: buf.len() });
+        }
+        // the RDATA parsers only get to see the message up to the end of
+        // this record, so a bad length cannot make them run into the next one
+        let mut rdata_cursor = start;
+        let data = match Content::from_rdata(kind, count, &buf[..end], &mut rdata_cursor) {
+            Ok(data) if rdata_cursor == end => data,
+            // keep RDATA that does not match its type as raw bytes
+            _ => Content::Other(buf[start..end].to_vec()),
+        };
+        *cursor = end;
+        Ok(Record {
+            name,
+            kind,
+            class,
+            ttl,
+            data,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Content {
+    IPv4(Ipv4Addr),
+    IPv6(Ipv6Addr),
+    DomainName(DomainName),
+    Soa(Soa),
+    Mx(Mx),
+    Srv(Srv),
+    Txt(Txt),
+    Other(Vec<u8>),
+}
+
+/// The start of authority of a zone (RFC 1035 section 3.3.13).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Soa {
+    /// the primary nameserver of the zone
+    pub mname: DomainName,
+    /// the mailbox of the person responsible, with the `@` as the first dot
+    pub rname: DomainName,
+    /// the version of the zone, compared with serial number arithmetic
+    pub serial: u32,
+    /// seconds between checks of the primary by secondaries
+    pub refresh: u32,
+    /// seconds before a secondary retries a failed refresh
+    pub retry: u32,
+    /// seconds after which a secondary stops answering without a refresh
+    pub expire: u32,
+    /// the TTL for negative answers from the zone (RFC 2308)
+    pub minimum: u32,
+}
+
+impl Soa {
+    pub fn write(&self, buf: &mut Vec<u8>, names: &mut NameTable) -> Result<(), DnsError> {
+        self.mname.write(buf, names)?;
+        self.rname.write(buf, names)?;
+        push_u32(buf, self.serial);
+        push_u32(buf, self.refresh);
+        push_u32(buf, self.retry);
+        push_u32(buf, self.expire);
+        push_u32(buf, self.minimum);
+        Ok(())
+    }
+}
+
+impl FromBytes for Soa {
+    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Result<Self, DnsError> {
+        let mname = DomainName::from_bytes(buf, cursor)?;
+        let rname = DomainName::from_bytes(buf, cursor)?;
+        let serial = pop_u32(buf, cursor)?;
+        let refresh = pop_u32(buf, cursor)?;
+        let retry = pop_u32(buf, cursor)?;
+        let expire = pop_u32(buf, cursor)?;
+        let minimum = pop_u32(buf, cursor)?;
+        Ok(Soa {
+            mname,
+            rname,
+            serial,
+            refresh,
+            retry,
+            expire,
+            minimum,
+        })
+    }
+}
+
+impl Display for Soa {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {} {}",
+            self.mname, self.rname, self.serial, self.refresh, self.retry, self.expire, self.minimum
+        )
+    }
+}
+
+/// A mail exchange for a domain (RFC 1035 section 3.3.9).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mx {
+    /// lower values are preferred
+    pub preference: u16,
+    pub exchange: DomainName,
+}
+
+impl Mx {
+    pub fn write(&self, buf: &mut Vec<u8>, names: &mut NameTable) -> Result<(), DnsError> {
+        push_u16(buf, self.preference);
+        self.exchange.write(buf, names)
+    }
+}
+
+impl FromBytes for Mx {
+    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Result<Self, DnsError> {
+        let preference = pop_u16(buf, cursor)?;
+        let exchange = DomainName::from_bytes(buf, cursor)?;
+        Ok(Mx {
+            preference,
+            exchange,
+        })
+    }
+}
+
+impl Display for Mx {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.preference, self.exchange)
+    }
+}
+
+/// The location of a service (RFC 2782).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Srv {
+    /// lower values are tried first
+    pub priority: u16,
+    /// the relative share of traffic among targets of equal priority
+    pub weight: u16,
+    pub port: u16,
+    pub target: DomainName,
+}
+
+impl Srv {
+    pub fn write(&self, buf: &mut Vec<u8>, _names: &mut NameTable) -> Result<(), DnsError> {
+        push_u16(buf, self.priority);
+        push_u16(buf, self.weight);
+        push_u16(buf, self.port);
+        // RFC 2782 forbids compressing the target
+        self.target.write(buf, &mut NameTable::uncompressed())
+    }
+}
+
+impl FromBytes for Srv {
+    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Result<Self, DnsError> {
+        let priority = pop_u16(buf, cursor)?;
+        let weight = pop_u16(buf, cursor)?;
+        let port = pop_u16(buf, cursor)?;
+        let target = DomainName::from_bytes(buf, cursor)?;
+        Ok(Srv {
+            priority,
+            weight,
+            port,
+            target,
+        })
+    }
+}
+
+impl Display for Srv {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.priority, self.weight, self.port, self.target
+        )
+    }
+}
+
+/// the longest character-string, as its length has to fit in a byte
+const MAX_CHARACTER_STRING: usize = 255;
+
+/// Free-form text as a list of character-strings (RFC 1035 section 3.3.14).
+///
+/// The strings are raw bytes; nothing guarantees that they are valid UTF-8
+/// or that a single value was not split across several of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Txt {
+    pub strings: Vec<Vec<u8>>,
+}
+
+impl Txt {
+    /// Splits `data` into as many character-strings as it takes to hold it.
+    pub fn new(data: &[u8]) -> Txt {
+        Txt {
+            strings: data
+                .chunks(MAX_CHARACTER_STRING)
+                .map(|chunk| chunk.to_vec())
+                .collect(),
+        }
+    }
+    /// The strings concatenated without separators, which is how SPF
+    /// (RFC 7208) and DKIM (RFC 6376) read records that span several.
+    pub fn join(&self) -> Vec<u8> {
+        self.strings.concat()
+    }
+    /// Like [`Txt::join`], with invalid UTF-8 replaced.
+    pub fn join_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.join()).into_owned()
+    }
+    /// Appends the strings, splitting any that are too long for one length
+    /// byte.
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        for string in self.strings.iter() {
+            if string.is_empty() {
+                buf.push(0);
+            }
+            for chunk in string.chunks(MAX_CHARACTER_STRING) {
+                buf.push(chunk.len() as u8);
+                buf.extend_from_slice(chunk);
+            }
+        }
+    }
+    /// Reads character-strings until `len` bytes of RDATA are used up.
+    fn from_rdata(buf: &[u8], cursor: &mut usize, len: usize) -> Result<Txt, DnsError> {
+        let end = *cursor + len;
+        let mut strings = Vec::new();
+        while *cursor < end {
+            let len = pop_u8(buf, cursor)?;
+            strings.push(pop_collection::<u8>(buf, cursor, len as usize)?);
+        }
+        Ok(Txt { strings })
+    }
+}
+
+impl Display for Txt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, string) in self.strings.iter().enumerate() {
+            if index > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "\"")?;
+            for byte in string.iter() {
+                match byte {
+                    b'"' | b'\\' => write!(f, "\\{}", *byte as char)?,
+                    0x20..=0x7e => write!(f, "{}", *byte as char)?,
+                    _ => write!(f, "\\{byte:03}")?,
+                }
+            }
+            write!(f, "\"")?;
+        }
+        Ok(())
+    }
+}
+
+impl Content {
+    /// Encodes the content as RDATA, without the length prefix.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, DnsError> {
+        let mut buf = Vec::new();
+        self.write(&mut buf, &mut NameTable::uncompressed())?;
+        Ok(buf)
+    }
+    /// Appends the RDATA to a message in `buf`, compressing names via `names`.
+    pub fn write(&self, buf: &mut Vec<u8>, names: &mut NameTable) -> Result<(), DnsError> {
+        match self {
+            Content::IPv4(ip) => buf.extend_from_slice(&ip.octets()),
+            Content::IPv6(ip) => buf.extend_from_slice(&ip.octets()),
+            Content::DomainName(dn) => dn.write(buf, names)?,
+            Content::Soa(soa) => soa.write(buf, names)?,
+            Content::Mx(mx) => mx.write(buf, names)?,
+            Content::Srv(srv) => srv.write(buf, names)?,
+            Content::Txt(txt) => txt.write(buf),
+            Content::Other(bytes) => buf.extend_from_slice(bytes),
+        }
+        Ok(())
+    }
+    /// Parses the RDATA of a record of type `kind` that is `count` bytes long.
+    fn from_rdata(
+        kind: Kind,
+        count: u16,
+        buf: &[u8],
+        cursor: &mut usize,
+    ) -> Result<Content, DnsError> {
         use Kind::*;
         let data = match kind {
             A => {
@@ -59,64 +362,73 @@ impl FromBytes for Record {
                 let domain = <DomainName as FromBytes>::from_bytes(buf, cursor)?;
                 Content::DomainName(domain)
             },
-            // MD => todo!(),
-            // MF => todo!(),
-            CNAME => {
+            MD | MF | MB | MG | MR => {
                 let domain = <DomainName as FromBytes>::from_bytes(buf, cursor)?;
                 Content::DomainName(domain)
             }
-            SOA => {
+            CNAME => {
                 let domain = <DomainName as FromBytes>::from_bytes(buf, cursor)?;
                 Content::DomainName(domain)
             }
-            // MB => todo!(),
-            // MG => todo!(),
-            // MR => todo!(),
+            SOA => {
+                let soa = <Soa as FromBytes>::from_bytes(buf, cursor)?;
+                Content::Soa(soa)
+            }
             // NULL => todo!(),
             // WKS => todo!(),
-            // PTR => todo!(),
+            PTR => {
+                let domain = <DomainName as FromBytes>::from_bytes(buf, cursor)?;
+                Content::DomainName(domain)
+            }
             // HINFO => todo!(),
-            // MINFO => todo!(),
-            // MX => todo!(),
+            MINFO => {
+                // two mailboxes, kept as raw bytes with any pointers expanded
+                // since they would be wrong at another offset (RFC 3597 §4)
+                let rmailbx = <DomainName as FromBytes>::from_bytes(buf, cursor)?;
+                let emailbx = <DomainName as FromBytes>::from_bytes(buf, cursor)?;
+                let mut data = rmailbx.to_bytes()?;
+                data.extend_from_slice(&emailbx.to_bytes()?);
+                Content::Other(data)
+            }
+            MX => {
+                let mx = <Mx as FromBytes>::from_bytes(buf, cursor)?;
+                Content::Mx(mx)
+            }
+            SRV => {
+                let srv = <Srv as FromBytes>::from_bytes(buf, cursor)?;
+                Content::Srv(srv)
+            }
             TXT => {
-                let text = pop_collection::<char>(buf, cursor, count as usize)?.iter().collect();
-                Content::Text(text)
-            },
+                let txt = Txt::from_rdata(buf, cursor, count as usize)?;
+                Content::Txt(txt)
+            }
             _ => {
                 let data = pop_collection(buf, cursor, count as usize)?;
                 Content::Other(data)
             }
         };
-        assert!(expected == *cursor, "Cursor was not moved as expected: {} vs {}", expected, *cursor);
-        Some(Record {
-            name,
-            kind,
-            class,
-            ttl,
-            data,
-        })
+        Ok(data)
     }
 }
 
-#[derive(Debug, Clone)]
-pub enum Content {
-    IPv4(Ipv4Addr),
-    IPv6(Ipv6Addr),
-    DomainName(DomainName),
-    Text(String),
-    Other(Vec<u8>),
-}
-
 impl Display for Content {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Content::IPv4(ip) => write!(f, "{ip}"),
             Content::IPv6(ip) => write!(f, "{ip}"),
             Content::DomainName(dn) => write!(f, "{dn}"),
-            Content::Text(text) => write!(f, "{text}"),
+            Content::Soa(soa) => write!(f, "{soa}"),
+            Content::Mx(mx) => write!(f, "{mx}"),
+            Content::Srv(srv) => write!(f, "{srv}"),
+            Content::Txt(txt) => write!(f, "{txt}"),
+            // the generic format of RFC 3597 section 5
             Content::Other(bytes) => {
+                write!(f, "\\# {}", bytes.len())?;
+                if !bytes.is_empty() {
+                    write!(f, " ")?;
+                }
                 for byte in bytes.iter() {
-                    write!(f, "{byte:02x} ")?;
+                    write!(f, "{byte:02x}")?;
                 }
                 Ok(())
             }
@@ -124,75 +436,107 @@ impl Display for Content {
     }
 }
 
-#[derive(Debug, Copy, Clone, Default)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
 pub enum Kind {
     /// illegal?
-    Undefined = 0,
+    Undefined,
     /// a host address
     #[default]
-    A = 1,
+    A,
     /// an ipv6 address
-    AAAA = 28,
+    AAAA,
     /// an authoritative name server
-    NS = 2,
-    /// a mail destination (Obsolete - use MX)     
-    MD = 3,
+    NS,
+    /// a mail destination (Obsolete - use MX)
+    MD,
     /// a mail forwarder (Obsolete - use MX)
-    MF = 4,
+    MF,
     /// the canonical name for an alias
-    CNAME = 5,
+    CNAME,
     /// marks the start of a zone of authority
-    SOA = 6,
+    SOA,
     /// a mailbox domain name (EXPERIMENTAL)
-    MB = 7,
+    MB,
     /// a mail group member (EXPERIMENTAL)
-    MG = 8,
+    MG,
     /// a mail rename domain name (EXPERIMENTAL)
-    MR = 9,
+    MR,
     /// a null RR (EXPERIMENTAL)
-    NULL = 10,
+    NULL,
     /// a well known service description
-    WKS = 11,
+    WKS,
     /// a domain name pointer
-    PTR = 12,
+    PTR,
     /// host information
-    HINFO = 13,
+    HINFO,
     /// mailbox or mail list information
-    MINFO = 14,
+    MINFO,
     /// mail exchange
-    MX = 15,
+    MX,
     /// text strings
-    TXT = 16,
+    TXT,
+    /// the location of a service
+    SRV,
+    /// the EDNS pseudo-record
+    OPT,
+    /// a type this crate has no special handling for, kept as its number
+    Unknown(u16),
 }
 
-impl TryFrom<u16> for Kind {
-    type Error = ();
-
-    fn try_from(value: u16) -> Result<Self, Self::Error> {
+impl From<u16> for Kind {
+    fn from(value: u16) -> Self {
         use Kind::*;
         match value {
-            0 => Ok(Undefined),
-            1 => Ok(A),
-            2 => Ok(NS),
-            3 => Ok(MD),
-            4 => Ok(MF),
-            5 => Ok(CNAME),
-            6 => Ok(SOA),
-            7 => Ok(MB),
-            8 => Ok(MG),
-            9 => Ok(MR),
-            10 => Ok(NULL),
-            11 => Ok(WKS),
-            12 => Ok(PTR),
-            13 => Ok(HINFO),
-            14 => Ok(MINFO),
-            15 => Ok(MX),
-            16 => Ok(TXT),
-            28 => Ok(AAAA),
-            _ => {
-                println!("failed to make Kind from {}", value);
-                Err(())
-            },
+            0 => Undefined,
+            1 => A,
+            2 => NS,
+            3 => MD,
+            4 => MF,
+            5 => CNAME,
+            6 => SOA,
+            7 => MB,
+            8 => MG,
+            9 => MR,
+            10 => NULL,
+            11 => WKS,
+            12 => PTR,
+            13 => HINFO,
+            14 => MINFO,
+            15 => MX,
+            16 => TXT,
+            28 => AAAA,
+            33 => SRV,
+            41 => OPT,
+            _ => Unknown(value),
+        }
+    }
+}
+
+impl From<Kind> for u16 {
+    fn from(kind: Kind) -> Self {
+        use Kind::*;
+        match kind {
+            Undefined => 0,
+            A => 1,
+            NS => 2,
+            MD => 3,
+            MF => 4,
+            CNAME => 5,
+            SOA => 6,
+            MB => 7,
+            MG => 8,
+            MR => 9,
+            NULL => 10,
+            WKS => 11,
+            PTR => 12,
+            HINFO => 13,
+            MINFO => 14,
+            MX => 15,
+            TXT => 16,
+            AAAA => 28,
+            SRV => 33,
+            OPT => 41,
+            Unknown(value) => value,
         }
     }
 }
@@ -218,31 +562,67 @@ impl Display for Kind {
             Kind::MINFO => "MINFO",
             Kind::MX => "MX",
             Kind::TXT => "TXT",
+            Kind::SRV => "SRV",
+            Kind::OPT => "OPT",
+            // RFC 3597 section 5
+            Kind::Unknown(value) => return write!(f, "TYPE{value}"),
         };
         write!(f, "{s}")
     }
 }
 
 impl FromBytes for Kind {
-    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Option<Kind> {
-        let num = pop_u16(buf, cursor)?;
-        num.try_into().ok()
+    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Result<Kind, DnsError> {
+        let kind = pop_u16(buf, cursor)?;
+        Ok(kind.into())
     }
 }
 
-#[derive(Debug, Copy, Clone, Default)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
 pub enum Class {
+    /// the Internet
     #[default]
-    Internet = 1,
+    Internet,
+    /// the CSNET class (Obsolete)
+    Csnet,
+    /// the CHAOS class, these days only used for server metadata such as
+    /// `version.bind`
+    Chaos,
+    /// Hesiod
+    Hesiod,
+    /// used in dynamic updates to delete records (RFC 2136)
+    None,
+    /// matches any class in queries and dynamic updates
+    Any,
+    /// any other value, including the requestor's UDP payload size that an
+    /// OPT pseudo-record carries in place of a class (RFC 6891)
+    Unknown(u16),
 }
-impl TryFrom<u16> for Class {
-    type Error = ();
 
-    fn try_from(value: u16) -> Result<Self, Self::Error> {
-        use Class::*;
+impl From<u16> for Class {
+    fn from(value: u16) -> Self {
         match value {
-            1 => Ok(Internet),
-            _ => Err(()),
+            1 => Class::Internet,
+            2 => Class::Csnet,
+            3 => Class::Chaos,
+            4 => Class::Hesiod,
+            254 => Class::None,
+            255 => Class::Any,
+            _ => Class::Unknown(value),
+        }
+    }
+}
+
+impl From<Class> for u16 {
+    fn from(class: Class) -> Self {
+        match class {
+            Class::Internet => 1,
+            Class::Csnet => 2,
+            Class::Chaos => 3,
+            Class::Hesiod => 4,
+            Class::None => 254,
+            Class::Any => 255,
+            Class::Unknown(value) => value,
         }
     }
 }
@@ -251,14 +631,21 @@ impl Display for Class {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match self {
             Class::Internet => "IN",
+            Class::Csnet => "CS",
+            Class::Chaos => "CH",
+            Class::Hesiod => "HS",
+            Class::None => "NONE",
+            Class::Any => "ANY",
+            // RFC 3597 section 5
+            Class::Unknown(value) => return write!(f, "CLASS{value}"),
         };
         write!(f, "{s}")
     }
 }
 
 impl FromBytes for Class {
-    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Option<Class> {
-        let num = pop_u16(buf, cursor)?;
-        num.try_into().ok()
+    fn from_bytes(buf: &[u8], cursor: &mut usize) -> Result<Class, DnsError> {
+        let class = pop_u16(buf, cursor)?;
+        Ok(class.into())
     }
 }
@@ -0,0 +1,123 @@
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::domain_name::DomainName;
+
+/// where the system resolver configuration lives
+pub const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+/// the most nameservers the C library uses, later ones are ignored
+const MAX_NAMESERVERS: usize = 3;
+/// the upper bounds the C library puts on the numeric options
+const MAX_NDOTS: usize = 15;
+const MAX_TIMEOUT: u64 = 30;
+const MAX_ATTEMPTS: usize = 5;
+
+/// The settings of a stub resolver, as read from resolv.conf(5).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvConf {
+    /// the recursive servers to ask, in order
+    pub nameservers: Vec<SocketAddr>,
+    /// the domains appended to names with fewer than `ndots` dots
+    pub search: Vec<DomainName>,
+    /// how many dots make a name worth trying as it is before the search list
+    pub ndots: usize,
+    /// how long to wait for each server
+    pub timeout: Duration,
+    /// how many times to go through the servers before giving up
+    pub attempts: usize,
+    /// spread queries over the servers instead of always starting with the
+    /// first
+    pub rotate: bool,
+}
+
+impl Default for ResolvConf {
+    fn default() -> Self {
+        ResolvConf {
+            nameservers: vec![],
+            search: vec![],
+            ndots: 1,
+            timeout: Duration::from_secs(5),
+            attempts: 2,
+            rotate: false,
+        }
+    }
+}
+
+impl ResolvConf {
+    /// Reads the system configuration from [`RESOLV_CONF`].
+    pub fn load() -> io::Result<ResolvConf> {
+        ResolvConf::from_file(RESOLV_CONF)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<ResolvConf> {
+        Ok(ResolvConf::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Parses the contents of a resolv.conf file.
+    ///
+    /// Like the C library, this never fails: lines that make no sense are
+    /// skipped, and without any nameserver the local host is asked.
+    pub fn parse(contents: &str) -> ResolvConf {
+        let mut config = ResolvConf::default();
+        for line in contents.lines() {
+            let line = line.split(['#', ';']).next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("nameserver") => {
+                    let address = words.next().and_then(|word| word.parse::<IpAddr>().ok());
+                    if let Some(address) = address {
+                        if config.nameservers.len() < MAX_NAMESERVERS {
+                            config.nameservers.push(SocketAddr::new(address, 53));
+                        }
+                    }
+                }
+                // whichever of `domain` and `search` comes last wins
+                Some("domain") => config.search = words.take(1).map(DomainName::new).collect(),
+                Some("search") => config.search = words.map(DomainName::new).collect(),
+                Some("options") => words.for_each(|option| config.set_option(option)),
+                _ => {}
+            }
+        }
+        if config.nameservers.is_empty() {
+            config.nameservers = vec![SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 53)];
+        }
+        config
+    }
+
+    fn set_option(&mut self, option: &str) {
+        let (name, value) = match option.split_once(':') {
+            Some((name, value)) => (name, value.parse::<u64>().ok()),
+            None => (option, None),
+        };
+        match (name, value) {
+            ("ndots", Some(n)) => self.ndots = (n as usize).min(MAX_NDOTS),
+            ("timeout", Some(n)) => self.timeout = Duration::from_secs(n.clamp(1, MAX_TIMEOUT)),
+            ("attempts", Some(n)) => self.attempts = (n as usize).clamp(1, MAX_ATTEMPTS),
+            ("rotate", None) => self.rotate = true,
+            _ => {}
+        }
+    }
+
+    /// The names to try for `name`, in order (see resolv.conf(5)).
+    ///
+    /// A name with a trailing dot is only tried as it is. Otherwise the
+    /// search list is tried after the name itself if it has at least `ndots`
+    /// dots, and before it if not.
+    pub fn candidates(&self, name: &str) -> Vec<DomainName> {
+        let absolute = DomainName::new(name);
+        if name.ends_with('.') {
+            return vec![absolute];
+        }
+        let searched = self.search.iter().map(|domain| absolute.join(domain));
+        let dots = absolute.labels().count().saturating_sub(1);
+        if dots >= self.ndots {
+            std::iter::once(absolute.clone()).chain(searched).collect()
+        } else {
+            searched.chain(std::iter::once(absolute.clone())).collect()
+        }
+    }
+}
@@ -0,0 +1,513 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use crate::cache::Cache;
+use crate::domain_name::DomainName;
+use crate::error::DnsError;
+use crate::lookup::Lookup;
+use crate::packet::{Flags, Packet, Question, Rcode};
+use crate::record::{Class, Content, Kind, Record};
+use crate::transport;
+use crate::ROOT_SERVERS;
+
+/// upper bound on the number of referrals followed for a single lookup
+const MAX_REFERRALS: usize = 32;
+/// upper bound on nested lookups of nameserver addresses
+const MAX_DEPTH: usize = 8;
+
+/// upper bound on the number of CNAME records followed for a single lookup
+pub(crate) const MAX_CNAMES: usize = 16;
+
+/// how long to wait for the first attempt at a query
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+/// how many times servers that did not answer are asked again
+const DEFAULT_RETRIES: usize = 2;
+/// the port nameservers listen on
+const DNS_PORT: u16 = 53;
+
+/// An iterative resolver, starting every lookup at the root servers or, with
+/// a cache, at the closest zone cut it knows of.
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    tcp: bool,
+    timeout: Duration,
+    retries: usize,
+    local_address: Option<IpAddr>,
+    randomize_case: bool,
+    cache: Option<Arc<Cache>>,
+    root_servers: Vec<SocketAddr>,
+    port: u16,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Resolver {
+            tcp: false,
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            local_address: None,
+            randomize_case: false,
+            cache: None,
+            root_servers: ROOT_SERVERS
+                .iter()
+                .map(|(_, ip, _, _)| SocketAddr::new(IpAddr::V4(*ip), DNS_PORT))
+                .collect(),
+            port: DNS_PORT,
+        }
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Resolver {
+        Resolver::default()
+    }
+
+    /// Sets how long to wait for a reply on the first attempt. Each retry
+    /// waits twice as long as the one before.
+    pub fn with_timeout(mut self, timeout: Duration) -> Resolver {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how many more times a server that timed out is asked before
+    /// giving up on it.
+    pub fn with_retries(mut self, retries: usize) -> Resolver {
+        self.retries = retries;
+        self
+    }
+
+    /// Sends queries over UDP from `address` rather than letting the
+    /// operating system choose, e.g. to pick an interface. Only servers of
+    /// the same address family are asked.
+    pub fn with_local_address(mut self, address: IpAddr) -> Resolver {
+        self.local_address = Some(address);
+        self
+    }
+
+    /// Randomises the letter case of every query name (DNS 0x20) and only
+    /// accepts responses that repeat it exactly. This makes spoofed responses
+    /// harder to guess, but servers that do not preserve the case will never
+    /// be heard.
+    pub fn with_case_randomization(mut self) -> Resolver {
+        self.randomize_case = true;
+        self
+    }
+
+    /// Answers from `cache` where possible and stores what is learnt in it.
+    /// Cached delegations let lookups start below the root.
+    pub fn with_cache(mut self, cache: Arc<Cache>) -> Resolver {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Starts lookups that the cache cannot shorten at `servers` instead of
+    /// the root servers, e.g. to resolve within a private namespace.
+    pub fn with_root_servers(mut self, servers: Vec<SocketAddr>) -> Resolver {
+        self.root_servers = servers;
+        self
+    }
+
+    /// Asks the nameservers found through referrals on `port` instead of 53.
+    pub fn with_port(mut self, port: u16) -> Resolver {
+        self.port = port;
+        self
+    }
+
+    /// Sends every query over TCP instead of trying UDP first.
+    pub fn with_tcp(mut self) -> Resolver {
+        self.tcp = true;
+        self
+    }
+
+    /// Resolves `domain` iteratively, starting at the root servers and
+    /// following NS referrals down the delegation chain until a server answers.
+    pub fn resolve(&self, domain: &str, kind: Kind) -> Result<Option<IpAddr>, DnsError> {
+        Ok(self.lookup(domain, kind)?.addresses().next())
+    }
+
+    /// Like [`Resolver::resolve`], but returns every matching record along
+    /// with the CNAME chain that led to it and the details of the final answer.
+    pub fn lookup(&self, domain: &str, kind: Kind) -> Result<Lookup, DnsError> {
+        let started = Instant::now();
+        let mut pending = Vec::new();
+        let mut name = DomainName::new(domain);
+        let mut aliases: Vec<DomainName> = Vec::new();
+
+        loop {
+            let (server, response) = match self.cached(&name, kind) {
+                Some(response) => (None, response),
+                None => {
+                    let (server, response) = self.resolve_iteratively(&name, kind, &mut pending)?;
+                    (Some(server.ip()), response)
+                }
+            };
+            let queried = name.clone();
+
+            // follow the chain as far as this response goes
+            loop {
+                let records = records(&response, &name, kind);
+                // NXDOMAIN is about the last name of the chain (RFC 6604)
+                if records.is_empty()
+                    && cname(&response, &name).is_none()
+                    && response.rcode() == Rcode::NXDomain
+                {
+                    return Err(DnsError::NameError { name });
+                }
+                if !records.is_empty() || (cname(&response, &name).is_none() && name == queried) {
+                    return Ok(Lookup {
+                        name: DomainName::new(domain),
+                        kind,
+                        aliases,
+                        canonical: name,
+                        records,
+                        server,
+                        rcode: response.rcode(),
+                        elapsed: started.elapsed(),
+                    });
+                }
+                let Some(target) = cname(&response, &name) else {
+                    break;
+                };
+                if target == name || aliases.contains(&target) {
+                    return Err(DnsError::Loop { name: target });
+                }
+                if aliases.len() >= MAX_CNAMES {
+                    return Err(DnsError::LimitExceeded {
+                        name: DomainName::new(domain),
+                    });
+                }
+                aliases.push(name);
+                name = target;
+            }
+        }
+    }
+
+    /// A response made up from the cached records of `kind` for `name`, or
+    /// failing that its cached CNAME or a cached negative answer.
+    fn cached(&self, name: &DomainName, kind: Kind) -> Option<Packet> {
+        let cache = self.cache.as_ref()?;
+        let answers = cache
+            .get(name, kind, Class::Internet)
+            .or_else(|| cache.get(name, Kind::CNAME, Class::Internet));
+        if let Some(answers) = answers {
+            return Some(answers.into_iter().fold(Packet::new(), Packet::with_answer));
+        }
+        let negative = cache.get_negative(name, kind, Class::Internet)?;
+        let mut response = Packet::new().with_authority(negative.soa);
+        response.set_rcode(negative.rcode);
+        Some(response)
+    }
+
+    /// Stores the records in `response` that the servers of `zone` can speak
+    /// for: the answers, the nameservers and glue of a referral, and whether
+    /// `domain` turned out not to exist or to have no records of `kind`.
+    fn remember(&self, zone: &DomainName, domain: &DomainName, kind: Kind, response: &Packet) {
+        let Some(cache) = &self.cache else {
+            return;
+        };
+        if response.answers.is_empty() {
+            // without an SOA there is no telling how long the answer holds
+            let soa = response.authorities.iter().find(|r| {
+                r.kind == Kind::SOA
+                    && r.name.is_subdomain_of(zone)
+                    && domain.is_subdomain_of(&r.name)
+            });
+            match (response.rcode(), soa) {
+                (Rcode::NXDomain, Some(soa)) => cache.insert_nxdomain(domain, Class::Internet, soa),
+                (Rcode::NoError, Some(soa)) => {
+                    cache.insert_nodata(domain, kind, Class::Internet, soa)
+                }
+                _ => {}
+            }
+        }
+        let in_zone = |r: &&Record| r.name.is_subdomain_of(zone);
+        let nameservers = response.authorities.iter().filter(|r| r.kind == Kind::NS);
+        let glue = response
+            .additionals
+            .iter()
+            .filter(|r| matches!(r.kind, Kind::A | Kind::AAAA));
+        let referral: Vec<Record> = nameservers.chain(glue).filter(in_zone).cloned().collect();
+        cache.insert(&referral);
+        // answers go in last so that they take the place of partial glue
+        let answers: Vec<Record> = response.answers.iter().filter(in_zone).cloned().collect();
+        cache.insert(&answers);
+    }
+
+    /// Walks the delegation chain for `domain` and returns the final response.
+    ///
+    /// `pending` holds the names whose resolution is in progress further up
+    /// the call stack, so that nameservers that can only be found through
+    /// themselves are detected instead of recursing forever.
+    fn resolve_iteratively(
+        &self,
+        domain: &DomainName,
+        kind: Kind,
+        pending: &mut Vec<DomainName>,
+    ) -> Result<(SocketAddr, Packet), DnsError> {
+        if pending.len() >= MAX_DEPTH {
+            return Err(DnsError::LimitExceeded {
+                name: domain.clone(),
+            });
+        }
+        if pending.contains(domain) {
+            return Err(DnsError::Loop {
+                name: domain.clone(),
+            });
+        }
+        pending.push(domain.clone());
+        let response = self.follow_referrals(domain, kind, pending);
+        pending.pop();
+        response
+    }
+
+    fn follow_referrals(
+        &self,
+        domain: &DomainName,
+        kind: Kind,
+        pending: &mut Vec<DomainName>,
+    ) -> Result<(SocketAddr, Packet), DnsError> {
+        let (mut zone, mut servers) = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.delegation(domain))
+            .map(|(zone, addresses)| (zone, self.on_port(addresses)))
+            .unwrap_or_else(|| (DomainName::empty(), self.root_servers.clone()));
+
+        for _ in 0..MAX_REFERRALS {
+            let (server, response) = self.ask(&servers, &zone, domain, kind)?;
+            self.remember(&zone, domain, kind, &response);
+
+            if !response.answers.is_empty() {
+                return Ok((server, response));
+            }
+            if response.rcode() == Rcode::NXDomain {
+                return Err(DnsError::NameError {
+                    name: domain.clone(),
+                });
+            }
+            let Some(referral) = Referral::from_packet(&response) else {
+                return Ok((server, response));
+            };
+            if referral.zone == zone
+                || !referral.zone.is_subdomain_of(&zone)
+                || !domain.is_subdomain_of(&referral.zone)
+            {
+                return Err(DnsError::LameReferral {
+                    zone: referral.zone,
+                });
+            }
+
+            servers = match referral.glue.is_empty() {
+                true => referral.resolve_nameservers(self, pending),
+                false => self.on_port(referral.glue),
+            };
+            zone = referral.zone;
+        }
+        Err(DnsError::LimitExceeded {
+            name: domain.clone(),
+        })
+    }
+
+    /// Queries the nameservers of `zone` in turn until one of them gives a
+    /// usable response.
+    ///
+    /// The servers are tried starting from a random one to spread the load,
+    /// still keeping IPv4 addresses ahead of IPv6 ones. Those that did not
+    /// answer at all are asked again in later rounds, with the timeout
+    /// doubling every round.
+    fn ask(
+        &self,
+        servers: &[SocketAddr],
+        zone: &DomainName,
+        domain: &DomainName,
+        kind: Kind,
+    ) -> Result<(SocketAddr, Packet), DnsError> {
+        let mut error = DnsError::NoNameservers { zone: zone.clone() };
+        let mut servers: Vec<SocketAddr> = servers
+            .iter()
+            .filter(|server| {
+                self.local_address
+                    .is_none_or(|local| local.is_ipv4() == server.is_ipv4())
+            })
+            .copied()
+            .collect();
+        if !servers.is_empty() {
+            let start = rand::thread_rng().gen_range(0..servers.len());
+            servers.rotate_left(start);
+            servers.sort_by_key(|server| server.is_ipv6());
+        }
+
+        let mut timeout = self.timeout;
+        for _ in 0..=self.retries {
+            let mut silent = Vec::new();
+            for server in servers {
+                // a name error is an authoritative statement too, no point
+                // in asking the others
+                match self.query(server, domain, kind, timeout) {
+                    Ok(response) => return Ok((server, response)),
+                    Err(err) => {
+                        if matches!(
+                            err,
+                            DnsError::Timeout | DnsError::Socket(_) | DnsError::CaseMismatch { .. }
+                        ) {
+                            silent.push(server);
+                        }
+                        error = err;
+                    }
+                }
+            }
+            if silent.is_empty() {
+                break;
+            }
+            servers = silent;
+            timeout = timeout.saturating_mul(2);
+        }
+        Err(error)
+    }
+
+    /// Sends a single non-recursive query to `server` and waits for the reply.
+    fn query(
+        &self,
+        server: SocketAddr,
+        domain: &DomainName,
+        kind: Kind,
+        timeout: Duration,
+    ) -> Result<Packet, DnsError> {
+        let name = match self.randomize_case {
+            true => domain.with_random_case(),
+            false => domain.clone(),
+        };
+        let question = Question::new().with_name(name.clone()).with_kind(kind);
+        let response = transport::query(
+            server,
+            question,
+            Flags::new(),
+            self.local_address,
+            self.tcp,
+            timeout,
+        )?;
+        // a spoofed response has to guess the case as well
+        if self.randomize_case && !response.questions.iter().all(|q| q.name().eq_exact(&name)) {
+            return Err(DnsError::CaseMismatch {
+                server: server.ip(),
+            });
+        }
+        Ok(response)
+    }
+
+    /// The addresses of nameservers found on the way, with the port to ask
+    /// them on.
+    fn on_port(&self, addresses: Vec<IpAddr>) -> Vec<SocketAddr> {
+        addresses
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, self.port))
+            .collect()
+    }
+}
+
+/// The target of the CNAME record for `name` in the answer section.
+pub(crate) fn cname(response: &Packet, name: &DomainName) -> Option<DomainName> {
+    response
+        .answers
+        .iter()
+        .find_map(|r| match (&r.kind, &r.data) {
+            (Kind::CNAME, Content::DomainName(target)) if r.name == *name => Some(target.clone()),
+            _ => None,
+        })
+}
+
+/// A delegation to the nameservers of `zone`, as found in the authority
+/// section of a response.
+struct Referral {
+    zone: DomainName,
+    nameservers: Vec<DomainName>,
+    glue: Vec<IpAddr>,
+}
+
+impl Referral {
+    fn from_packet(response: &Packet) -> Option<Referral> {
+        let mut zone = None;
+        let nameservers: Vec<DomainName> = response
+            .authorities
+            .iter()
+            .filter_map(|r| match (&r.kind, &r.data) {
+                (Kind::NS, Content::DomainName(ns)) => {
+                    zone.get_or_insert_with(|| r.name.clone());
+                    Some(ns.clone())
+                }
+                _ => None,
+            })
+            .collect();
+
+        // glue addresses, IPv4 first
+        let mut glue: Vec<IpAddr> = response
+            .additionals
+            .iter()
+            .filter(|r| nameservers.contains(&r.name))
+            .filter_map(|r| match r.data {
+                Content::IPv4(ip) => Some(IpAddr::V4(ip)),
+                Content::IPv6(ip) => Some(IpAddr::V6(ip)),
+                _ => None,
+            })
+            .collect();
+        glue.sort_by_key(|ip| ip.is_ipv6());
+
+        Some(Referral {
+            zone: zone?,
+            nameservers,
+            glue,
+        })
+    }
+
+    /// Looks up the addresses of the nameservers one at a time, stopping at
+    /// the first one that resolves.
+    fn resolve_nameservers(
+        &self,
+        resolver: &Resolver,
+        pending: &mut Vec<DomainName>,
+    ) -> Vec<SocketAddr> {
+        self.nameservers
+            .iter()
+            .find_map(|ns| {
+                if let Some(cache) = &resolver.cache {
+                    let addresses = cache.addresses(ns);
+                    if !addresses.is_empty() {
+                        return Some(addresses);
+                    }
+                }
+                let (_, response) = resolver.resolve_iteratively(ns, Kind::A, pending).ok()?;
+                let addresses = addresses(&response, ns);
+                (!addresses.is_empty()).then_some(addresses)
+            })
+            .map(|addresses| resolver.on_port(addresses))
+            .unwrap_or_default()
+    }
+}
+
+/// All records of `kind` in the answer section of `response` that belong to
+/// `name`.
+pub(crate) fn records(response: &Packet, name: &DomainName, kind: Kind) -> Vec<Record> {
+    response
+        .answers
+        .iter()
+        .filter(|r| r.kind == kind && r.name == *name)
+        .cloned()
+        .collect()
+}
+
+/// All addresses in the answer section of `response` that belong to `name`.
+fn addresses(response: &Packet, name: &DomainName) -> Vec<IpAddr> {
+    response
+        .answers
+        .iter()
+        .filter(|r| r.name == *name)
+        .filter_map(|r| match r.data {
+            Content::IPv4(ip) => Some(IpAddr::V4(ip)),
+            Content::IPv6(ip) => Some(IpAddr::V6(ip)),
+            _ => None,
+        })
+        .collect()
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 fn pair(num: u16) -> (u8, u8) {
     let hi = ((num >> 8) & 0xff) as u8;
     let lo = (num & 0xff) as u8;
@@ -9,3 +11,57 @@ pub fn push_u16(buf: &mut Vec<u8>, num: u16) {
     buf.push(a);
     buf.push(b);
 }
+
+pub fn push_u32(buf: &mut Vec<u8>, num: u32) {
+    push_u16(buf, (num >> 16) as u16);
+    push_u16(buf, num as u16);
+}
+
+pub fn push_u64(buf: &mut Vec<u8>, num: u64) {
+    push_u32(buf, (num >> 32) as u32);
+    push_u32(buf, num as u32);
+}
+
+/// pointers only have 14 bits for the offset
+const MAX_POINTER: usize = 0x3fff;
+
+/// The offsets of the names already written to a message, so that later
+/// names can point at a matching suffix instead of repeating it
+/// (RFC 1035 section 4.1.4).
+///
+/// Suffixes are matched exactly, case included, so that compression never
+/// changes what a name looks like after decoding.
+#[derive(Debug)]
+pub struct NameTable {
+    offsets: Option<HashMap<Vec<Vec<u8>>, u16>>,
+}
+
+impl NameTable {
+    pub fn new() -> NameTable {
+        NameTable {
+            offsets: Some(HashMap::new()),
+        }
+    }
+    /// A table that never compresses, for encoding items on their own.
+    pub fn uncompressed() -> NameTable {
+        NameTable { offsets: None }
+    }
+    /// The offset of an earlier occurrence of `suffix`.
+    pub fn find(&self, suffix: &[Vec<u8>]) -> Option<u16> {
+        self.offsets.as_ref()?.get(suffix).copied()
+    }
+    /// Remembers that `suffix` starts at `offset`, if a pointer can reach it.
+    pub fn insert(&mut self, suffix: &[Vec<u8>], offset: usize) {
+        if let Some(offsets) = self.offsets.as_mut() {
+            if offset <= MAX_POINTER {
+                offsets.entry(suffix.to_vec()).or_insert(offset as u16);
+            }
+        }
+    }
+}
+
+impl Default for NameTable {
+    fn default() -> Self {
+        NameTable::new()
+    }
+}
@@ -0,0 +1,130 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use crate::domain_name::DomainName;
+use crate::error::DnsError;
+use crate::lookup::Lookup;
+use crate::packet::{Flags, Packet, Question, Rcode};
+use crate::record::Kind;
+use crate::resolv_conf::ResolvConf;
+use crate::resolver::{cname, records, MAX_CNAMES};
+use crate::transport;
+
+/// A stub resolver, handing every lookup to the recursive servers of a
+/// [`ResolvConf`] the way the C library does.
+#[derive(Debug)]
+pub struct StubResolver {
+    config: ResolvConf,
+    /// the server to start with next when rotating
+    next: AtomicUsize,
+}
+
+impl StubResolver {
+    pub fn new(config: ResolvConf) -> StubResolver {
+        StubResolver {
+            config,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// A stub resolver set up from /etc/resolv.conf.
+    pub fn from_system() -> std::io::Result<StubResolver> {
+        Ok(StubResolver::new(ResolvConf::load()?))
+    }
+
+    pub fn config(&self) -> &ResolvConf {
+        &self.config
+    }
+
+    /// Looks up `domain`, trying the names from the search list in turn
+    /// until one of them exists and has records of `kind`.
+    pub fn lookup(&self, domain: &str, kind: Kind) -> Result<Lookup, DnsError> {
+        let started = Instant::now();
+        let mut result = Err(DnsError::NameError {
+            name: DomainName::new(domain),
+        });
+        for name in self.config.candidates(domain) {
+            result = self.lookup_name(&name, kind, started);
+            match &result {
+                // try the next name only if this one did not work out
+                Ok(lookup) if lookup.records.is_empty() => {}
+                Err(DnsError::NameError { .. }) => {}
+                _ => return result,
+            }
+        }
+        result
+    }
+
+    fn lookup_name(
+        &self,
+        name: &DomainName,
+        kind: Kind,
+        started: Instant,
+    ) -> Result<Lookup, DnsError> {
+        let (server, response) = self.query(name, kind)?;
+
+        // the server has already followed the chain, it only needs walking
+        let mut canonical = name.clone();
+        let mut aliases = Vec::new();
+        while records(&response, &canonical, kind).is_empty() {
+            let Some(target) = cname(&response, &canonical) else {
+                break;
+            };
+            if target == canonical || aliases.contains(&target) {
+                return Err(DnsError::Loop { name: target });
+            }
+            if aliases.len() >= MAX_CNAMES {
+                return Err(DnsError::LimitExceeded { name: name.clone() });
+            }
+            aliases.push(std::mem::replace(&mut canonical, target));
+        }
+        // NXDOMAIN is about the last name of the chain (RFC 6604)
+        if response.rcode() == Rcode::NXDomain && records(&response, &canonical, kind).is_empty() {
+            return Err(DnsError::NameError { name: canonical });
+        }
+
+        Ok(Lookup {
+            name: name.clone(),
+            kind,
+            aliases,
+            records: records(&response, &canonical, kind),
+            canonical,
+            server: Some(server.ip()),
+            rcode: response.rcode(),
+            elapsed: started.elapsed(),
+        })
+    }
+
+    /// Sends a recursive query for `name` to the configured servers, going
+    /// through all of them up to `attempts` times.
+    fn query(&self, name: &DomainName, kind: Kind) -> Result<(SocketAddr, Packet), DnsError> {
+        let question = Question::new().with_name(name.clone()).with_kind(kind);
+        let servers = &self.config.nameservers;
+        let start = match self.config.rotate {
+            true => self.next.fetch_add(1, Ordering::Relaxed),
+            false => 0,
+        };
+        let mut error = DnsError::NoNameservers {
+            zone: DomainName::empty(),
+        };
+        for _ in 0..self.config.attempts {
+            for index in 0..servers.len() {
+                let server = servers[(start + index) % servers.len()];
+                let response = transport::query(
+                    server,
+                    question.clone(),
+                    Flags::new().with_recusion(),
+                    None,
+                    false,
+                    self.config.timeout,
+                );
+                match response {
+                    Ok(response) => return Ok((server, response)),
+                    Err(err) => error = err,
+                }
+            }
+        }
+        Err(error)
+    }
+}
@@ -0,0 +1,144 @@
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::edns::Edns;
+use crate::error::DnsError;
+use crate::packet::{Flags, Packet, Question, Rcode};
+
+/// Asks `server` a single question and returns its response if that is
+/// NOERROR or NXDOMAIN, and an error for any other response code.
+///
+/// The query carries an OPT record, unless the server rejects it because it
+/// predates EDNS, in which case it is asked again without one. See
+/// [`exchange`] for `local` and `tcp`.
+pub fn query(
+    server: SocketAddr,
+    question: Question,
+    flags: Flags,
+    local: Option<IpAddr>,
+    tcp: bool,
+    timeout: Duration,
+) -> Result<Packet, DnsError> {
+    let query = Packet::new()
+        .with_flags(flags)
+        .with_question(question.clone())
+        .with_edns(Edns::new());
+    let mut response = exchange(local, server, &query, timeout, tcp)?;
+    if matches!(response.rcode(), Rcode::FormErr | Rcode::NotImp) && response.edns.is_none() {
+        let query = Packet::new().with_flags(flags).with_question(question);
+        response = exchange(local, server, &query, timeout, tcp)?;
+    }
+
+    match response.rcode() {
+        Rcode::NoError | Rcode::NXDomain => Ok(response),
+        Rcode::ServFail => Err(DnsError::ServerFailure {
+            server: server.ip(),
+        }),
+        rcode => Err(DnsError::Rcode {
+            server: server.ip(),
+            rcode,
+        }),
+    }
+}
+
+/// Sends `query` to `server` over UDP from `local`, falling back to TCP when
+/// the response did not fit in a datagram, or over TCP right away if `tcp`
+/// is set.
+pub fn exchange(
+    local: Option<IpAddr>,
+    server: SocketAddr,
+    query: &Packet,
+    timeout: Duration,
+    tcp: bool,
+) -> Result<Packet, DnsError> {
+    if tcp {
+        return self::tcp(server, query, timeout);
+    }
+    let response = udp(local, server, query, timeout)?;
+    if response.flags.is_truncated() {
+        return self::tcp(server, query, timeout);
+    }
+    Ok(response)
+}
+
+/// Sends `query` to `server` over UDP and waits up to `timeout` for the reply.
+///
+/// Every query gets a fresh socket on a port picked by the operating system,
+/// bound to `local` if given. Datagrams that do not come from `server` or do
+/// not answer `query` are dropped, and waiting goes on until the timeout.
+pub fn udp(
+    local: Option<IpAddr>,
+    server: SocketAddr,
+    query: &Packet,
+    timeout: Duration,
+) -> Result<Packet, DnsError> {
+    let local = local.unwrap_or(match server {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    });
+    let socket = UdpSocket::bind(SocketAddr::new(local, 0))?;
+    let deadline = Instant::now() + timeout;
+
+    {
+        let buf = query.to_bytes()?;
+        socket.send_to(&buf, server)?;
+    }
+    {
+        // without EDNS, responses over UDP are limited to 512 bytes
+        let size = query
+            .edns
+            .as_ref()
+            .map_or(512, |edns| edns.payload_size.max(512));
+        let mut buf = vec![0u8; size as usize];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(DnsError::Timeout);
+            }
+            socket.set_read_timeout(Some(remaining))?;
+            let (count, addr) = socket.recv_from(&mut buf)?;
+            if addr != server {
+                continue;
+            }
+            match Packet::from_bytes(&buf[..count]) {
+                Ok(response) if response.is_response_to(query) => return Ok(response),
+                // someone else's or garbage, keep waiting for ours
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Sends `query` to `server` over TCP and waits for the reply. `timeout`
+/// applies to connecting and to each read and write separately.
+///
+/// Each message on the stream is preceded by its length as a two byte
+/// integer (RFC 1035 §4.2.2).
+pub fn tcp(server: SocketAddr, query: &Packet, timeout: Duration) -> Result<Packet, DnsError> {
+    let mut stream = TcpStream::connect_timeout(&server, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    {
+        let buf = query.to_bytes()?;
+        let len = u16::try_from(buf.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "query too long for TCP"))?;
+        let mut message = Vec::with_capacity(buf.len() + 2);
+        message.extend_from_slice(&len.to_be_bytes());
+        message.extend_from_slice(&buf);
+        stream.write_all(&message)?;
+    }
+    {
+        loop {
+            let mut len = [0u8; 2];
+            stream.read_exact(&mut len)?;
+            let mut buf = vec![0u8; u16::from_be_bytes(len) as usize];
+            stream.read_exact(&mut buf)?;
+            let response = Packet::from_bytes(&buf)?;
+            if response.is_response_to(query) {
+                return Ok(response);
+            }
+        }
+    }
+}
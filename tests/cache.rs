@@ -0,0 +1,294 @@
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use weekend_dns::cache::Cache;
+use weekend_dns::domain_name::DomainName;
+use weekend_dns::packet::Rcode;
+use weekend_dns::record::{Class, Content, Kind, Record, Soa};
+
+fn a(name: &str, ttl: i32, last: u8) -> Record {
+    Record {
+        name: DomainName::new(name),
+        kind: Kind::A,
+        class: Class::Internet,
+        ttl,
+        data: Content::IPv4(Ipv4Addr::new(192, 0, 2, last)),
+    }
+}
+
+fn ns(zone: &str, ttl: i32, server: &str) -> Record {
+    Record {
+        name: DomainName::new(zone),
+        kind: Kind::NS,
+        class: Class::Internet,
+        ttl,
+        data: Content::DomainName(DomainName::new(server)),
+    }
+}
+
+#[test]
+fn records_are_cached_per_set() {
+    let cache = Cache::default();
+    cache.insert(&[
+        a("example.com", 300, 1),
+        a("example.com", 300, 2),
+        a("example.org", 300, 3),
+    ]);
+    assert_eq!(cache.len(), 2);
+
+    let records = cache
+        .get(&DomainName::new("EXAMPLE.com"), Kind::A, Class::Internet)
+        .unwrap();
+    assert_eq!(records.len(), 2);
+    assert!(records.iter().all(|r| (299..=300).contains(&r.ttl)));
+    assert_eq!(
+        cache.get(&DomainName::new("example.com"), Kind::AAAA, Class::Internet),
+        None
+    );
+    assert_eq!(
+        cache.get(&DomainName::new("example.com"), Kind::A, Class::Chaos),
+        None
+    );
+}
+
+#[test]
+fn records_expire_with_their_lowest_ttl() {
+    let cache = Cache::default();
+    cache.insert(&[
+        a("example.com", 1, 1),
+        a("example.com", 300, 2),
+        a("example.org", 0, 3),
+    ]);
+    assert_eq!(cache.len(), 1);
+    assert!(cache
+        .get(&DomainName::new("example.com"), Kind::A, Class::Internet)
+        .is_some());
+
+    thread::sleep(Duration::from_millis(1100));
+    assert_eq!(
+        cache.get(&DomainName::new("example.com"), Kind::A, Class::Internet),
+        None
+    );
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn least_recently_used_sets_are_evicted() {
+    let cache = Cache::new(2);
+    cache.insert(&[a("one.example", 300, 1)]);
+    cache.insert(&[a("two.example", 300, 2)]);
+    assert!(cache
+        .get(&DomainName::new("one.example"), Kind::A, Class::Internet)
+        .is_some());
+    cache.insert(&[a("three.example", 300, 3)]);
+
+    assert_eq!(cache.len(), 2);
+    assert!(cache
+        .get(&DomainName::new("one.example"), Kind::A, Class::Internet)
+        .is_some());
+    assert!(cache
+        .get(&DomainName::new("two.example"), Kind::A, Class::Internet)
+        .is_none());
+    assert!(cache
+        .get(&DomainName::new("three.example"), Kind::A, Class::Internet)
+        .is_some());
+}
+
+#[test]
+fn delegation_finds_the_closest_zone_with_addresses() {
+    let cache = Cache::default();
+    cache.insert(&[
+        ns("com", 300, "a.gtld-servers.net"),
+        a("a.gtld-servers.net", 300, 1),
+    ]);
+    // no address known for this one
+    cache.insert(&[ns("example.com", 300, "ns.example.net")]);
+
+    let (zone, servers) = cache
+        .delegation(&DomainName::new("www.example.com"))
+        .unwrap();
+    assert_eq!(zone, DomainName::new("com"));
+    assert_eq!(servers, [IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))]);
+
+    cache.insert(&[a("ns.example.net", 300, 2)]);
+    let (zone, _) = cache
+        .delegation(&DomainName::new("www.example.com"))
+        .unwrap();
+    assert_eq!(zone, DomainName::new("example.com"));
+    assert_eq!(cache.delegation(&DomainName::new("example.org")), None);
+}
+
+#[test]
+fn cache_can_be_shared_between_threads() {
+    let cache = Arc::new(Cache::new(64));
+    let handles: Vec<_> = (0..8u8)
+        .map(|n| {
+            let cache = cache.clone();
+            thread::spawn(move || {
+                for i in 0..16u8 {
+                    cache.insert(&[a(&format!("host{i}.thread{n}.example"), 300, i)]);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(cache.len(), 64);
+}
+
+fn soa(zone: &str, ttl: i32, minimum: u32) -> Record {
+    Record {
+        name: DomainName::new(zone),
+        kind: Kind::SOA,
+        class: Class::Internet,
+        ttl,
+        data: Content::Soa(Soa {
+            mname: DomainName::new("ns.example.com"),
+            rname: DomainName::new("hostmaster.example.com"),
+            serial: 2024010101,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum,
+        }),
+    }
+}
+
+#[test]
+fn nxdomain_covers_every_kind() {
+    let cache = Cache::default();
+    let name = DomainName::new("missing.example.com");
+    cache.insert_nxdomain(&name, Class::Internet, &soa("example.com", 3600, 300));
+
+    for kind in [Kind::A, Kind::AAAA, Kind::MX] {
+        let negative = cache.get_negative(&name, kind, Class::Internet).unwrap();
+        assert_eq!(negative.rcode, Rcode::NXDomain);
+        // the SOA minimum is lower than its TTL
+        assert!((299..=300).contains(&negative.soa.ttl));
+    }
+    assert_eq!(cache.get(&name, Kind::A, Class::Internet), None);
+    assert_eq!(
+        cache.get_negative(&DomainName::new("example.com"), Kind::A, Class::Internet),
+        None
+    );
+
+    // a positive answer for the name replaces the negative one
+    cache.insert(&[a("missing.example.com", 300, 1)]);
+    assert_eq!(cache.get_negative(&name, Kind::MX, Class::Internet), None);
+}
+
+#[test]
+fn nodata_covers_one_kind() {
+    let cache = Cache::default();
+    let name = DomainName::new("example.com");
+    cache.insert_nodata(
+        &name,
+        Kind::AAAA,
+        Class::Internet,
+        &soa("example.com", 60, 300),
+    );
+
+    let negative = cache
+        .get_negative(&name, Kind::AAAA, Class::Internet)
+        .unwrap();
+    assert_eq!(negative.rcode, Rcode::NoError);
+    // the SOA TTL is lower than its minimum
+    assert!((59..=60).contains(&negative.soa.ttl));
+    assert_eq!(cache.get_negative(&name, Kind::A, Class::Internet), None);
+}
+
+#[test]
+fn negative_answers_expire() {
+    let cache = Cache::default();
+    let name = DomainName::new("missing.example.com");
+    cache.insert_nxdomain(&name, Class::Internet, &soa("example.com", 3600, 1));
+    cache.insert_nodata(
+        &name,
+        Kind::A,
+        Class::Internet,
+        &soa("example.com", 3600, 0),
+    );
+    assert_eq!(cache.len(), 1);
+
+    thread::sleep(Duration::from_millis(1100));
+    assert_eq!(cache.get_negative(&name, Kind::A, Class::Internet), None);
+}
+
+#[test]
+fn cache_survives_a_restart() {
+    let path = std::env::temp_dir().join(format!("weekend-dns-cache-{}", std::process::id()));
+    let cache = Cache::default();
+    cache.insert(&[a("example.com", 300, 1), a("example.com", 300, 2)]);
+    cache.insert(&[ns("com", 3600, "a.gtld-servers.net")]);
+    cache.insert(&[a("short.example", 1, 3)]);
+    cache.insert_nxdomain(
+        &DomainName::new("missing.example.com"),
+        Class::Internet,
+        &soa("example.com", 3600, 300),
+    );
+    cache.save(&path).unwrap();
+
+    // long enough for the short-lived set to be gone
+    thread::sleep(Duration::from_millis(1100));
+    let reloaded = Cache::default();
+    assert_eq!(reloaded.load(&path).unwrap(), 3);
+    std::fs::remove_file(&path).unwrap();
+
+    let records = reloaded
+        .get(&DomainName::new("example.com"), Kind::A, Class::Internet)
+        .unwrap();
+    assert_eq!(records.len(), 2);
+    assert!(records.iter().all(|r| (297..=300).contains(&r.ttl)));
+    assert!(reloaded
+        .get(&DomainName::new("com"), Kind::NS, Class::Internet)
+        .is_some());
+    assert!(reloaded
+        .get(&DomainName::new("short.example"), Kind::A, Class::Internet)
+        .is_none());
+    let negative = reloaded
+        .get_negative(
+            &DomainName::new("missing.example.com"),
+            Kind::TXT,
+            Class::Internet,
+        )
+        .unwrap();
+    assert_eq!(negative.rcode, Rcode::NXDomain);
+    assert_eq!(negative.soa.data, soa("example.com", 0, 300).data);
+}
+
+#[test]
+fn garbage_is_not_loaded() {
+    let path = std::env::temp_dir().join(format!("weekend-dns-garbage-{}", std::process::id()));
+    std::fs::write(&path, b"WDNSCACHE\x01\x07example").unwrap();
+    let cache = Cache::default();
+    let err = cache.load(&path).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn far_future_expiry_is_capped() {
+    let path = std::env::temp_dir().join(format!("weekend-dns-future-{}", std::process::id()));
+    let mut file = b"WDNSCACHE\x01".to_vec();
+    file.extend_from_slice(&DomainName::new("example.com").to_bytes().unwrap());
+    // has a kind, A, IN
+    file.extend_from_slice(&[1, 0, 1, 0, 1]);
+    file.extend_from_slice(&u64::MAX.to_be_bytes());
+    // positive, one record
+    file.extend_from_slice(&[0, 0, 1]);
+    file.extend_from_slice(&a("example.com", 300, 1).to_bytes().unwrap());
+    std::fs::write(&path, file).unwrap();
+
+    let cache = Cache::default();
+    let loaded = cache.load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.unwrap(), 1);
+    let records = cache
+        .get(&DomainName::new("example.com"), Kind::A, Class::Internet)
+        .unwrap();
+    assert!(records[0].ttl > i32::MAX - 5);
+}
@@ -0,0 +1,285 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use proptest::prelude::*;
+use weekend_dns::deserialization::FromBytes;
+use weekend_dns::domain_name::DomainName;
+use weekend_dns::edns::{Edns, EdnsOption};
+use weekend_dns::packet::{Flags, Packet, Question};
+use weekend_dns::record::{Class, Content, Kind, Mx, Record, Soa, Srv, Txt};
+
+/// A label of raw bytes, written out with zone file escapes.
+fn label() -> impl Strategy<Value = String> {
+    prop::collection::vec(any::<u8>(), 1..=20).prop_map(|bytes| {
+        bytes
+            .iter()
+            .map(|byte| match byte {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => (*byte as char).to_string(),
+                _ => format!("\\{byte:03}"),
+            })
+            .collect()
+    })
+}
+
+fn domain_name() -> impl Strategy<Value = DomainName> {
+    prop::collection::vec(label(), 0..6).prop_map(|labels| DomainName::new(&labels.join(".")))
+}
+
+fn kind() -> impl Strategy<Value = Kind> {
+    prop_oneof![
+        Just(Kind::Undefined),
+        Just(Kind::A),
+        Just(Kind::AAAA),
+        Just(Kind::NS),
+        Just(Kind::MD),
+        Just(Kind::MF),
+        Just(Kind::CNAME),
+        Just(Kind::SOA),
+        Just(Kind::MB),
+        Just(Kind::MG),
+        Just(Kind::MR),
+        Just(Kind::NULL),
+        Just(Kind::WKS),
+        Just(Kind::PTR),
+        Just(Kind::HINFO),
+        Just(Kind::MINFO),
+        Just(Kind::MX),
+        Just(Kind::TXT),
+        Just(Kind::SRV),
+        any::<u16>().prop_map(Kind::from),
+    ]
+    .prop_filter("OPT records are carried in Packet::edns", |kind| *kind != Kind::OPT)
+}
+
+fn class() -> impl Strategy<Value = Class> {
+    prop_oneof![
+        Just(Class::Internet),
+        Just(Class::Chaos),
+        Just(Class::None),
+        Just(Class::Any),
+        any::<u16>().prop_map(Class::from),
+    ]
+}
+
+/// Content of the shape that decoding produces for `kind`.
+fn content(kind: Kind) -> BoxedStrategy<Content> {
+    match kind {
+        Kind::A => any::<[u8; 4]>()
+            .prop_map(|octets| Content::IPv4(Ipv4Addr::from(octets)))
+            .boxed(),
+        Kind::AAAA => any::<[u8; 16]>()
+            .prop_map(|octets| Content::IPv6(Ipv6Addr::from(octets)))
+            .boxed(),
+        Kind::NS | Kind::MD | Kind::MF | Kind::CNAME | Kind::MB | Kind::MG | Kind::MR | Kind::PTR => {
+            domain_name().prop_map(Content::DomainName).boxed()
+        }
+        Kind::SOA => (domain_name(), domain_name(), any::<[u32; 5]>())
+            .prop_map(|(mname, rname, [serial, refresh, retry, expire, minimum])| {
+                Content::Soa(Soa {
+                    mname,
+                    rname,
+                    serial,
+                    refresh,
+                    retry,
+                    expire,
+                    minimum,
+                })
+            })
+            .boxed(),
+        Kind::MINFO => (domain_name(), domain_name())
+            .prop_map(|(rmailbx, emailbx)| {
+                let mut data = rmailbx.to_bytes().unwrap();
+                data.extend_from_slice(&emailbx.to_bytes().unwrap());
+                Content::Other(data)
+            })
+            .boxed(),
+        Kind::MX => (any::<u16>(), domain_name())
+            .prop_map(|(preference, exchange)| Content::Mx(Mx { preference, exchange }))
+            .boxed(),
+        Kind::SRV => (any::<[u16; 3]>(), domain_name())
+            .prop_map(|([priority, weight, port], target)| {
+                Content::Srv(Srv {
+                    priority,
+                    weight,
+                    port,
+                    target,
+                })
+            })
+            .boxed(),
+        Kind::TXT => prop::collection::vec(prop::collection::vec(any::<u8>(), 0..=255), 1..4)
+            .prop_map(|strings| Content::Txt(Txt { strings }))
+            .boxed(),
+        _ => prop::collection::vec(any::<u8>(), 0..64)
+            .prop_map(Content::Other)
+            .boxed(),
+    }
+}
+
+fn record() -> impl Strategy<Value = Record> {
+    (domain_name(), kind(), class(), any::<i32>()).prop_flat_map(|(name, kind, class, ttl)| {
+        content(kind).prop_map(move |data| Record {
+            name: name.clone(),
+            kind,
+            class,
+            ttl,
+            data,
+        })
+    })
+}
+
+fn question() -> impl Strategy<Value = Question> {
+    (domain_name(), kind(), class()).prop_map(|(name, kind, class)| {
+        Question::new()
+            .with_name(name)
+            .with_kind(kind)
+            .with_class(class)
+    })
+}
+
+fn edns_option() -> impl Strategy<Value = EdnsOption> {
+    prop_oneof![
+        prop::collection::vec(any::<u8>(), 0..16).prop_map(EdnsOption::Nsid),
+        (0..=32u8, any::<u8>(), any::<[u8; 4]>()).prop_map(|(source_prefix, scope_prefix, mut ip)| {
+            // only the bytes covered by the prefix are sent
+            for byte in ip.iter_mut().skip((source_prefix as usize).div_ceil(8)) {
+                *byte = 0;
+            }
+            EdnsOption::ClientSubnet {
+                source_prefix,
+                scope_prefix,
+                address: IpAddr::V4(Ipv4Addr::from(ip)),
+            }
+        }),
+        (0..=128u8, any::<u8>(), any::<[u8; 16]>()).prop_map(|(source_prefix, scope_prefix, mut ip)| {
+            for byte in ip.iter_mut().skip((source_prefix as usize).div_ceil(8)) {
+                *byte = 0;
+            }
+            EdnsOption::ClientSubnet {
+                source_prefix,
+                scope_prefix,
+                address: IpAddr::V6(Ipv6Addr::from(ip)),
+            }
+        }),
+        (
+            any::<[u8; 8]>(),
+            prop_oneof![Just(vec![]), prop::collection::vec(any::<u8>(), 8..=32)]
+        )
+            .prop_map(|(client, server)| EdnsOption::Cookie { client, server }),
+        prop::collection::vec(Just(0u8), 0..64).prop_map(EdnsOption::Padding),
+        (
+            any::<u16>().prop_filter("typed options", |code| ![3, 8, 10, 12].contains(code)),
+            prop::collection::vec(any::<u8>(), 0..16)
+        )
+            .prop_map(|(code, data)| EdnsOption::Unknown { code, data }),
+    ]
+}
+
+fn edns() -> impl Strategy<Value = Edns> {
+    (
+        any::<u16>(),
+        any::<u8>(),
+        any::<u8>(),
+        any::<bool>(),
+        0..0x8000u16,
+        prop::collection::vec(edns_option(), 0..4),
+    )
+        .prop_map(|(payload_size, extended_rcode, version, dnssec_ok, z, options)| Edns {
+            payload_size,
+            extended_rcode,
+            version,
+            dnssec_ok,
+            z,
+            options,
+        })
+}
+
+fn packet() -> impl Strategy<Value = Packet> {
+    (
+        any::<u16>(),
+        any::<u16>(),
+        prop::collection::vec(question(), 0..3),
+        prop::collection::vec(record(), 0..4),
+        prop::collection::vec(record(), 0..4),
+        prop::collection::vec(record(), 0..4),
+        prop::option::of(edns()),
+    )
+        .prop_map(
+            |(id, flags, questions, answers, authorities, additionals, edns)| Packet {
+                id,
+                flags: Flags::from(flags),
+                questions,
+                answers,
+                authorities,
+                additionals,
+                edns,
+            },
+        )
+}
+
+#[test]
+fn escapes_over_255_are_kept_as_text() {
+    assert_eq!(
+        DomainName::new("\\044abc").labels().collect::<Vec<_>>(),
+        [b",abc"]
+    );
+    let name = DomainName::new("\\300abc.example");
+    assert_eq!(name.labels().next(), Some(&b"300abc"[..]));
+    assert_eq!(name, DomainName::new("300abc.example"));
+}
+
+proptest! {
+    #[test]
+    fn domain_name_round_trips(name in domain_name()) {
+        let bytes = name.to_bytes().unwrap();
+        let mut cursor = 0;
+        prop_assert_eq!(DomainName::from_bytes(&bytes, &mut cursor).unwrap(), name.clone());
+        prop_assert_eq!(cursor, bytes.len());
+        prop_assert_eq!(DomainName::new(&name.to_string()), name);
+    }
+
+    #[test]
+    fn question_round_trips(question in question()) {
+        let bytes = question.to_bytes().unwrap();
+        let mut cursor = 0;
+        prop_assert_eq!(Question::from_bytes(&bytes, &mut cursor).unwrap(), question);
+        prop_assert_eq!(cursor, bytes.len());
+    }
+
+    #[test]
+    fn record_round_trips(record in record()) {
+        let bytes = record.to_bytes().unwrap();
+        let mut cursor = 0;
+        prop_assert_eq!(Record::from_bytes(&bytes, &mut cursor).unwrap(), record);
+        prop_assert_eq!(cursor, bytes.len());
+    }
+
+    #[test]
+    fn packet_round_trips(packet in packet()) {
+        let bytes = packet.to_bytes().unwrap();
+        prop_assert_eq!(Packet::from_bytes(&bytes).unwrap(), packet);
+    }
+
+    #[test]
+    fn edns_round_trips(edns in edns()) {
+        prop_assert_eq!(Edns::from_record(&edns.to_record()), Some(edns));
+    }
+
+    #[test]
+    fn compression_never_grows_a_packet(packet in packet()) {
+        let uncompressed: usize = 12
+            + packet.edns.iter().map(|e| e.to_record().to_bytes().unwrap().len()).sum::<usize>()
+            + packet.questions.iter().map(|q| q.to_bytes().unwrap().len()).sum::<usize>()
+            + packet
+                .answers
+                .iter()
+                .chain(packet.authorities.iter())
+                .chain(packet.additionals.iter())
+                .map(|r| r.to_bytes().unwrap().len())
+                .sum::<usize>();
+        prop_assert!(packet.to_bytes().unwrap().len() <= uncompressed);
+    }
+
+    #[test]
+    fn decoding_arbitrary_bytes_does_not_panic(bytes in prop::collection::vec(any::<u8>(), 0..512)) {
+        let _ = Packet::from_bytes(&bytes).map(|packet| packet.to_string());
+    }
+}
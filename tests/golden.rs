@@ -0,0 +1,366 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use weekend_dns::domain_name::DomainName;
+use weekend_dns::edns::{Edns, EdnsOption};
+use weekend_dns::error::DnsError;
+use weekend_dns::packet::{Flags, Opcode, Packet, Question, Rcode};
+use weekend_dns::record::{Class, Content, Kind, Mx, Soa, Srv, Txt};
+
+/// Decodes a response, checks that encoding it again gives back the exact
+/// same bytes, and returns it.
+fn decode(bytes: &[u8]) -> Packet {
+    let packet = Packet::from_bytes(bytes).unwrap();
+    assert_eq!(packet.to_bytes().unwrap(), bytes);
+    packet
+}
+
+#[test]
+fn a_answer() {
+    let packet = decode(include_bytes!("data/a-answer"));
+    assert_eq!(packet.id, 0x3c4d);
+    assert_eq!(u16::from(packet.flags), 0x8400);
+    assert!(packet.flags.is_response());
+    assert!(packet.flags.is_authoritative());
+    assert!(!packet.flags.recursion_desired());
+    assert_eq!(packet.opcode(), Opcode::Query);
+    assert_eq!(packet.rcode(), Rcode::NoError);
+    assert_eq!(packet.flags.to_string(), "R-QUERY-AA-tc-rd-ra-z-ad-cd-NOERROR");
+    assert_eq!(
+        packet.questions,
+        [Question::new().with_domain_name("example.com").with_kind(Kind::A)]
+    );
+    assert_eq!(packet.answers.len(), 1);
+    let answer = &packet.answers[0];
+    assert_eq!(answer.name, DomainName::new("example.com"));
+    assert_eq!(answer.ttl, 3600);
+    assert_eq!(answer.data, Content::IPv4(Ipv4Addr::new(93, 184, 215, 14)));
+}
+
+#[test]
+fn aaaa_answer() {
+    let packet = decode(include_bytes!("data/aaaa-answer"));
+    assert_eq!(
+        packet.answers[0].data,
+        Content::IPv6("2606:2800:21f:cb07:6820:80da:af6b:8b2c".parse::<Ipv6Addr>().unwrap())
+    );
+}
+
+#[test]
+fn cname_chain() {
+    let packet = decode(include_bytes!("data/cname-chain"));
+    assert_eq!(packet.answers.len(), 2);
+    assert_eq!(packet.answers[0].name, DomainName::new("www.github.com"));
+    assert_eq!(packet.answers[0].kind, Kind::CNAME);
+    assert_eq!(packet.answers[0].data, Content::DomainName(DomainName::new("github.com")));
+    assert_eq!(packet.answers[1].name, DomainName::new("github.com"));
+    assert_eq!(packet.answers[1].data, Content::IPv4(Ipv4Addr::new(140, 82, 121, 4)));
+}
+
+#[test]
+fn root_referral() {
+    let packet = decode(include_bytes!("data/root-referral"));
+    assert!(packet.answers.is_empty());
+    assert_eq!(packet.authorities.len(), 4);
+    for (record, server) in packet.authorities.iter().zip(["a", "b", "c", "d"]) {
+        assert_eq!(record.name, DomainName::new("com"));
+        assert_eq!(record.kind, Kind::NS);
+        assert_eq!(record.ttl, 172800);
+        assert_eq!(
+            record.data,
+            Content::DomainName(DomainName::new(&format!("{server}.gtld-servers.net")))
+        );
+    }
+    assert_eq!(packet.additionals.len(), 8);
+    assert_eq!(packet.additionals[0].name, DomainName::new("a.gtld-servers.net"));
+    assert_eq!(packet.additionals[0].data, Content::IPv4(Ipv4Addr::new(192, 5, 6, 30)));
+    assert_eq!(packet.additionals[4].kind, Kind::AAAA);
+}
+
+#[test]
+fn glueless_referral() {
+    let packet = decode(include_bytes!("data/glueless-referral"));
+    assert_eq!(packet.authorities.len(), 2);
+    assert!(packet.additionals.is_empty());
+    assert_eq!(
+        packet.authorities[1].data,
+        Content::DomainName(DomainName::new("b.iana-servers.net"))
+    );
+}
+
+#[test]
+fn nxdomain_with_soa() {
+    let packet = decode(include_bytes!("data/nxdomain-soa"));
+    assert_eq!(packet.rcode(), Rcode::NXDomain);
+    assert!(packet.answers.is_empty());
+    assert_eq!(packet.authorities.len(), 1);
+    assert_eq!(packet.authorities[0].kind, Kind::SOA);
+    assert_eq!(
+        packet.authorities[0].data,
+        Content::Soa(Soa {
+            mname: DomainName::new("ns.icann.org"),
+            rname: DomainName::new("noc.dns.icann.org"),
+            serial: 2024081440,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 3600,
+        })
+    );
+    assert_eq!(
+        packet.authorities[0].to_string(),
+        "example.com SOA IN 3600 ns.icann.org noc.dns.icann.org 2024081440 7200 3600 1209600 3600"
+    );
+}
+
+#[test]
+fn txt_answer() {
+    let packet = decode(include_bytes!("data/txt-answer"));
+    assert_eq!(packet.answers.len(), 2);
+    assert!(packet.answers.iter().all(|r| r.kind == Kind::TXT));
+    assert_eq!(
+        packet.answers[0].data,
+        Content::Txt(Txt {
+            strings: vec![b"v=spf1 include:_spf.google.com ~all".to_vec()],
+        })
+    );
+    let Content::Txt(dkim) = &packet.answers[1].data else {
+        panic!("not a TXT record");
+    };
+    assert_eq!(dkim.strings.len(), 2);
+    assert_eq!(
+        dkim.join_lossy(),
+        "v=DKIM1; k=rsa; p=MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEAoD2yLvGvJsnB0Bq"
+    );
+    assert_eq!(
+        packet.answers[1].to_string(),
+        "google.com TXT IN 3600 \"v=DKIM1; k=rsa; p=MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEA\" \"oD2yLvGvJsnB0Bq\""
+    );
+}
+
+#[test]
+fn txt_strings_are_binary_safe() {
+    let txt = Txt {
+        strings: vec![vec![0, b'"', 0xff], vec![], vec![b'\\'; 300]],
+    };
+    let mut bytes = Vec::new();
+    txt.write(&mut bytes);
+    // the empty string survives, the long one is split in two
+    assert_eq!(bytes[..5], [3, 0, b'"', 0xff, 0]);
+    assert_eq!(bytes[5], 255);
+    assert_eq!(bytes[5 + 256], 45);
+    assert_eq!(bytes.len(), 5 + 256 + 46);
+    assert_eq!(
+        Txt { strings: vec![vec![0, b'"', 0xff]] }.to_string(),
+        r#""\000\"\255""#
+    );
+}
+
+#[test]
+fn mx_answer() {
+    let packet = decode(include_bytes!("data/mx-answer"));
+    assert_eq!(packet.answers.len(), 3);
+    assert!(packet.answers.iter().all(|r| r.kind == Kind::MX));
+    assert_eq!(
+        packet.answers[1].data,
+        Content::Mx(Mx {
+            preference: 10,
+            exchange: DomainName::new("alt1.gmail-smtp-in.l.google.com"),
+        })
+    );
+    assert_eq!(
+        packet.answers[0].to_string(),
+        "gmail.com MX IN 3600 5 gmail-smtp-in.l.google.com"
+    );
+}
+
+#[test]
+fn ptr_answer() {
+    let packet = decode(include_bytes!("data/ptr-answer"));
+    assert_eq!(packet.answers[0].name, DomainName::new("8.8.8.8.in-addr.arpa"));
+    assert_eq!(packet.answers[0].kind, Kind::PTR);
+    assert_eq!(packet.answers[0].data, Content::DomainName(DomainName::new("dns.google")));
+}
+
+#[test]
+fn srv_answer() {
+    // the server compressed the target, which we never do (RFC 2782), so
+    // the encoding differs but must decode to the same message
+    let packet = Packet::from_bytes(include_bytes!("data/srv-answer")).unwrap();
+    let encoded = packet.to_bytes().unwrap();
+    assert_eq!(encoded.len(), 84);
+    assert_eq!(Packet::from_bytes(&encoded).unwrap(), packet);
+    assert_eq!(
+        packet.answers[0].data,
+        Content::Srv(Srv {
+            priority: 31,
+            weight: 30,
+            port: 5269,
+            target: DomainName::new("hermes2.jabber.org"),
+        })
+    );
+    assert_eq!(
+        packet.answers[0].to_string(),
+        "_xmpp-server._tcp.jabber.org SRV IN 900 31 30 5269 hermes2.jabber.org"
+    );
+}
+
+#[test]
+fn truncated_flag_without_records() {
+    let packet = decode(include_bytes!("data/truncated"));
+    assert!(packet.flags.is_truncated());
+    assert!(packet.answers.is_empty());
+}
+
+#[test]
+fn cut_short_responses_are_errors() {
+    let bytes = include_bytes!("data/root-referral");
+    for len in 0..bytes.len() {
+        assert!(
+            matches!(Packet::from_bytes(&bytes[..len]), Err(DnsError::Truncated { .. })),
+            "{len}"
+        );
+    }
+}
+
+#[test]
+fn forward_pointers_are_errors() {
+    let mut bytes = include_bytes!("data/a-answer").to_vec();
+    // the answer's owner name points back at the question, make it point
+    // at itself instead
+    let answer = 12 + 13 + 4;
+    bytes[answer + 1] = answer as u8;
+    assert!(matches!(
+        Packet::from_bytes(&bytes),
+        Err(DnsError::ForwardPointer { offset, pointer }) if offset == answer && pointer == answer
+    ));
+}
+
+#[test]
+fn names_too_long_to_encode_are_errors() {
+    let longest = "a".repeat(63);
+    assert_eq!(DomainName::new(&longest).to_bytes().unwrap().len(), 65);
+    let label = DomainName::new(&"a".repeat(64));
+    assert!(matches!(label.to_bytes(), Err(DnsError::BadName { .. })));
+
+    // four labels of 63 bytes take 4 * 64 + 1 = 257 bytes on the wire
+    let name = DomainName::new(&[longest.as_str(); 4].join("."));
+    assert!(matches!(name.to_bytes(), Err(DnsError::BadName { .. })));
+    let packet = Packet::new().with_question(Question::new().with_domain_name(&name.to_string()));
+    assert!(matches!(packet.to_bytes(), Err(DnsError::BadName { .. })));
+}
+
+#[test]
+fn flags_follow_the_rfc_bit_layout() {
+    let mut flags = Flags::new().with_recusion();
+    assert_eq!(u16::from(flags), 0x0100);
+    flags.set_response(true);
+    flags.set_opcode(Opcode::Update);
+    flags.set_truncated(true);
+    flags.set_checking_disabled(true);
+    flags.set_rcode(Rcode::Refused);
+    // QR, opcode 5, TC, RD, CD, rcode 5
+    assert_eq!(u16::from(flags), 0b1010_1011_0001_0101);
+    assert_eq!(flags.opcode(), Opcode::Update);
+    assert_eq!(flags.rcode(), Rcode::Refused);
+    assert_eq!(flags.to_string(), "R-UPDATE-aa-TC-RD-ra-z-ad-CD-REFUSED");
+}
+
+#[test]
+fn unknown_types_are_kept_opaque() {
+    // an HTTPS record (type 65) for example.com, after the a-answer question
+    let mut bytes = include_bytes!("data/a-answer")[..29].to_vec();
+    bytes[7] = 1;
+    bytes.extend_from_slice(&[0xc0, 12, 0, 65, 0, 1, 0, 0, 1, 44, 0, 4, 0, 1, 0, 0]);
+    let packet = decode(&bytes);
+    let record = &packet.answers[0];
+    assert_eq!(record.kind, Kind::Unknown(65));
+    assert_eq!(record.data, Content::Other(vec![0, 1, 0, 0]));
+    assert_eq!(record.to_string(), "example.com TYPE65 IN 300 \\# 4 00010000");
+}
+
+#[test]
+fn names_in_old_mail_types_are_expanded() {
+    // an MR and an MINFO record for example.com, with names pointing back
+    // at the question, after the a-answer question
+    let mut bytes = include_bytes!("data/a-answer")[..29].to_vec();
+    bytes[7] = 2;
+    bytes.extend_from_slice(&[0xc0, 12, 0, 9, 0, 1, 0, 0, 1, 44, 0, 6]);
+    bytes.extend_from_slice(&[3, b'n', b'e', b'w', 0xc0, 12]);
+    bytes.extend_from_slice(&[0xc0, 12, 0, 14, 0, 1, 0, 0, 1, 44, 0, 4]);
+    bytes.extend_from_slice(&[0xc0, 12, 0xc0, 12]);
+    let packet = Packet::from_bytes(&bytes).unwrap();
+    assert_eq!(
+        packet.answers[0].data,
+        Content::DomainName(DomainName::new("new.example.com"))
+    );
+    let example = DomainName::new("example.com").to_bytes().unwrap();
+    assert_eq!(
+        packet.answers[1].data,
+        Content::Other([&example[..], &example[..]].concat())
+    );
+
+    // the names still mean the same once the question has gone
+    let mut moved = packet.clone();
+    moved.questions.clear();
+    assert_eq!(Packet::from_bytes(&moved.to_bytes().unwrap()).unwrap(), moved);
+}
+
+#[test]
+fn chaos_class() {
+    let packet = decode(include_bytes!("data/chaos-version"));
+    assert_eq!(
+        packet.questions,
+        [Question::new()
+            .with_domain_name("version.bind")
+            .with_kind(Kind::TXT)
+            .with_class(Class::Chaos)]
+    );
+    assert_eq!(packet.answers[0].class, Class::Chaos);
+    assert_eq!(packet.answers[0].to_string(), "version.bind TXT CH 0 \"9.18.24\"");
+}
+
+#[test]
+fn edns_is_taken_out_of_the_additionals() {
+    let packet = decode(include_bytes!("data/https-edns"));
+    assert_eq!(packet.answers[0].kind, Kind::Unknown(65));
+    assert!(packet.additionals.is_empty());
+    assert_eq!(packet.edns, Some(Edns::new()));
+}
+
+#[test]
+fn edns_options() {
+    let edns = Edns::new()
+        .with_dnssec_ok()
+        .with_option(EdnsOption::Nsid(vec![]))
+        .with_option(EdnsOption::ClientSubnet {
+            source_prefix: 24,
+            scope_prefix: 0,
+            address: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 0)),
+        })
+        .with_option(EdnsOption::Cookie {
+            client: [1, 2, 3, 4, 5, 6, 7, 8],
+            server: vec![],
+        })
+        .with_option(EdnsOption::Padding(vec![0; 3]));
+    let mut packet = Packet::new().with_id(0x1234).with_edns(edns.clone());
+    packet.set_rcode(Rcode::BadVers);
+    let bytes = packet.to_bytes().unwrap();
+    assert_eq!(
+        bytes[12..],
+        [
+            0, // root
+            0, 41, // OPT
+            0x04, 0xd0, // 1232 bytes
+            1, 0, 0x80, 0, // extended rcode 1, version 0, DO
+            0, 34, // RDLENGTH
+            0, 3, 0, 0, // NSID
+            0, 8, 0, 7, 0, 1, 24, 0, 192, 0, 2, // ECS
+            0, 10, 0, 8, 1, 2, 3, 4, 5, 6, 7, 8, // cookie
+            0, 12, 0, 3, 0, 0, 0, // padding
+        ]
+    );
+    let decoded = decode(&bytes);
+    assert_eq!(decoded.rcode(), Rcode::BadVers);
+    assert_eq!(decoded.flags.rcode(), Rcode::NoError);
+    assert_eq!(decoded.edns.unwrap().options, edns.options);
+}
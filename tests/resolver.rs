@@ -0,0 +1,349 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use weekend_dns::cache::Cache;
+use weekend_dns::domain_name::DomainName;
+use weekend_dns::error::DnsError;
+use weekend_dns::packet::{Packet, Question, Rcode};
+use weekend_dns::record::{Class, Content, Kind, Record, Soa};
+use weekend_dns::resolver::Resolver;
+
+fn record(name: &str, kind: Kind, data: Content) -> Record {
+    Record {
+        name: DomainName::new(name),
+        kind,
+        class: Class::Internet,
+        ttl: 300,
+        data,
+    }
+}
+
+fn a(name: &str, address: Ipv4Addr) -> Record {
+    record(name, Kind::A, Content::IPv4(address))
+}
+
+fn name(name: &str, kind: Kind, target: &str) -> Record {
+    record(name, kind, Content::DomainName(DomainName::new(target)))
+}
+
+fn soa(zone: &str) -> Record {
+    record(
+        zone,
+        Kind::SOA,
+        Content::Soa(Soa {
+            mname: DomainName::new("ns.example"),
+            rname: DomainName::new("hostmaster.example"),
+            serial: 1,
+            refresh: 3600,
+            retry: 600,
+            expire: 86400,
+            minimum: 60,
+        }),
+    )
+}
+
+/// Binds a socket on each of the loopback addresses 127.0.0.`last`, all on
+/// the same port, since referrals only carry addresses.
+fn bind(lasts: &[u8]) -> (u16, Vec<UdpSocket>) {
+    loop {
+        let first = UdpSocket::bind((Ipv4Addr::new(127, 0, 0, lasts[0]), 0)).unwrap();
+        let port = first.local_addr().unwrap().port();
+        let rest: Result<Vec<UdpSocket>, _> = lasts[1..]
+            .iter()
+            .map(|last| UdpSocket::bind((Ipv4Addr::new(127, 0, 0, *last), port)))
+            .collect();
+        if let Ok(rest) = rest {
+            return (port, std::iter::once(first).chain(rest).collect());
+        }
+    }
+}
+
+/// Answers every query on `socket` with what `zone` makes of it, and counts
+/// the queries.
+fn serve(socket: UdpSocket, zone: fn(&DomainName, &mut Packet)) -> Arc<AtomicUsize> {
+    let queries = Arc::new(AtomicUsize::new(0));
+    let count = queries.clone();
+    thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        loop {
+            let (len, source) = socket.recv_from(&mut buf).unwrap();
+            count.fetch_add(1, Ordering::SeqCst);
+            let query = Packet::from_bytes(&buf[..len]).unwrap();
+            let mut response = query.clone();
+            response.edns = None;
+            response.flags.set_response(true);
+            zone(query.questions[0].name(), &mut response);
+            socket
+                .send_to(&response.to_bytes().unwrap(), source)
+                .unwrap();
+        }
+    });
+    queries
+}
+
+/// Counts the queries on `socket` without ever answering.
+fn ignore(socket: UdpSocket) -> Arc<AtomicUsize> {
+    let queries = Arc::new(AtomicUsize::new(0));
+    let count = queries.clone();
+    thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        while socket.recv_from(&mut buf).is_ok() {
+            count.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+    queries
+}
+
+/// Delegates example. with glue, and glueless. to a nameserver in example.
+fn root(name: &DomainName, response: &mut Packet) {
+    if name.is_subdomain_of(&DomainName::new("example")) {
+        response.authorities = vec![self::name("example", Kind::NS, "ns.example")];
+        response.additionals = vec![a("ns.example", Ipv4Addr::new(127, 0, 0, 2))];
+    } else if name.is_subdomain_of(&DomainName::new("glueless")) {
+        response.authorities = vec![self::name("glueless", Kind::NS, "ns.example")];
+    } else {
+        response.set_rcode(Rcode::NXDomain);
+        response.authorities = vec![soa("")];
+    }
+}
+
+/// The example. zone, served from 127.0.0.2.
+fn example(name: &DomainName, response: &mut Packet) {
+    let www = a("www.example", Ipv4Addr::new(192, 0, 2, 1));
+    response.answers = match name.to_string().as_str() {
+        "www.example" => vec![www],
+        "alias.example" => vec![self::name("alias.example", Kind::CNAME, "www.example"), www],
+        "far.example" => vec![self::name("far.example", Kind::CNAME, "host.glueless")],
+        "ns.example" => vec![a("ns.example", Ipv4Addr::new(127, 0, 0, 3))],
+        "dangling.example" => {
+            response.set_rcode(Rcode::NXDomain);
+            response.authorities = vec![soa("example")];
+            vec![self::name("dangling.example", Kind::CNAME, "gone.example")]
+        }
+        _ => {
+            response.set_rcode(Rcode::NXDomain);
+            response.authorities = vec![soa("example")];
+            vec![]
+        }
+    };
+}
+
+/// The glueless. zone, served from 127.0.0.3.
+fn glueless(name: &DomainName, response: &mut Packet) {
+    if *name == DomainName::new("host.glueless") {
+        response.answers = vec![a("host.glueless", Ipv4Addr::new(192, 0, 2, 3))];
+    } else {
+        response.set_rcode(Rcode::NXDomain);
+        response.authorities = vec![soa("glueless")];
+    }
+}
+
+/// Answers with an address for any name, but echoes the question in lower
+/// case.
+fn lowercase(name: &DomainName, response: &mut Packet) {
+    let lowered = name.to_string().to_ascii_lowercase();
+    response.questions = vec![Question::new().with_domain_name(&lowered)];
+    response.answers = vec![a(&lowered, Ipv4Addr::new(192, 0, 2, 1))];
+}
+
+/// The made-up namespace above, with the number of queries each server got.
+struct Namespace {
+    port: u16,
+    root: Arc<AtomicUsize>,
+    example: Arc<AtomicUsize>,
+    glueless: Arc<AtomicUsize>,
+}
+
+impl Namespace {
+    fn start() -> Namespace {
+        let (port, mut sockets) = bind(&[1, 2, 3]);
+        let glueless = serve(sockets.pop().unwrap(), self::glueless);
+        let example = serve(sockets.pop().unwrap(), self::example);
+        let root = serve(sockets.pop().unwrap(), self::root);
+        Namespace {
+            port,
+            root,
+            example,
+            glueless,
+        }
+    }
+
+    fn root_server(&self) -> SocketAddr {
+        SocketAddr::from((Ipv4Addr::new(127, 0, 0, 1), self.port))
+    }
+
+    fn resolver(&self) -> Resolver {
+        Resolver::new()
+            .with_root_servers(vec![self.root_server()])
+            .with_port(self.port)
+            .with_timeout(Duration::from_millis(500))
+    }
+}
+
+#[test]
+fn referrals_are_followed_with_glue() {
+    let namespace = Namespace::start();
+    let lookup = namespace.resolver().lookup("www.example", Kind::A).unwrap();
+    assert_eq!(
+        lookup.addresses().collect::<Vec<_>>(),
+        [IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))]
+    );
+    assert_eq!(lookup.server, Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2))));
+    assert_eq!(namespace.root.load(Ordering::SeqCst), 1);
+    assert_eq!(namespace.example.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn glueless_nameservers_are_resolved_first() {
+    let namespace = Namespace::start();
+    let lookup = namespace
+        .resolver()
+        .lookup("host.glueless", Kind::A)
+        .unwrap();
+    assert_eq!(
+        lookup.addresses().collect::<Vec<_>>(),
+        [IpAddr::V4(Ipv4Addr::new(192, 0, 2, 3))]
+    );
+    assert_eq!(lookup.server, Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 3))));
+    // once for host.glueless and once for ns.example
+    assert_eq!(namespace.root.load(Ordering::SeqCst), 2);
+    assert_eq!(namespace.example.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn cnames_are_followed_within_and_across_responses() {
+    let namespace = Namespace::start();
+    let resolver = namespace.resolver();
+
+    let lookup = resolver.lookup("alias.example", Kind::A).unwrap();
+    assert_eq!(lookup.aliases, [DomainName::new("alias.example")]);
+    assert_eq!(lookup.canonical, DomainName::new("www.example"));
+    assert_eq!(lookup.records.len(), 1);
+    assert_eq!(namespace.example.load(Ordering::SeqCst), 1);
+
+    let lookup = resolver.lookup("far.example", Kind::A).unwrap();
+    assert_eq!(lookup.aliases, [DomainName::new("far.example")]);
+    assert_eq!(lookup.canonical, DomainName::new("host.glueless"));
+    assert_eq!(
+        lookup.addresses().collect::<Vec<_>>(),
+        [IpAddr::V4(Ipv4Addr::new(192, 0, 2, 3))]
+    );
+    assert_eq!(namespace.glueless.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn missing_targets_are_reported_at_the_end_of_the_chain() {
+    let namespace = Namespace::start();
+    assert!(matches!(
+        namespace.resolver().lookup("dangling.example", Kind::A),
+        Err(DnsError::NameError { name }) if name == DomainName::new("gone.example")
+    ));
+    assert_eq!(namespace.example.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn missing_names_are_answered_from_the_cache() {
+    let namespace = Namespace::start();
+    let resolver = namespace.resolver().with_cache(Arc::new(Cache::default()));
+
+    for _ in 0..2 {
+        assert!(matches!(
+            resolver.lookup("missing.example", Kind::A),
+            Err(DnsError::NameError { name }) if name == DomainName::new("missing.example")
+        ));
+    }
+    assert_eq!(namespace.root.load(Ordering::SeqCst), 1);
+    assert_eq!(namespace.example.load(Ordering::SeqCst), 1);
+
+    // the delegation is cached too, so the root is not asked again
+    let lookup = resolver.lookup("www.example", Kind::A).unwrap();
+    assert_eq!(lookup.records.len(), 1);
+    assert_eq!(namespace.root.load(Ordering::SeqCst), 1);
+    assert_eq!(namespace.example.load(Ordering::SeqCst), 2);
+    let lookup = resolver.lookup("www.example", Kind::A).unwrap();
+    assert_eq!(lookup.server, None);
+    assert_eq!(namespace.example.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn randomized_case_must_be_echoed() {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap();
+    let queries = serve(server, lowercase);
+    let resolver = Resolver::new()
+        .with_root_servers(vec![address])
+        .with_timeout(Duration::from_millis(100))
+        .with_retries(1);
+    // long enough that the random case is never all lower case
+    let name = "a-name-with-many-letters.example";
+
+    let lookup = resolver.lookup(name, Kind::A).unwrap();
+    assert_eq!(lookup.records.len(), 1);
+    assert!(matches!(
+        resolver.with_case_randomization().lookup(name, Kind::A),
+        Err(DnsError::CaseMismatch { .. })
+    ));
+    // treated like no answer, so asked again
+    assert_eq!(queries.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn silent_servers_are_skipped() {
+    let namespace = Namespace::start();
+    let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let resolver = namespace
+        .resolver()
+        .with_root_servers(vec![silent.local_addr().unwrap(), namespace.root_server()])
+        .with_timeout(Duration::from_millis(100));
+
+    let lookup = resolver.lookup("www.example", Kind::A).unwrap();
+    assert_eq!(lookup.records.len(), 1);
+    assert_eq!(namespace.root.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn silent_servers_are_retried_with_a_growing_timeout() {
+    let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = silent.local_addr().unwrap();
+    let queries = ignore(silent);
+    let resolver = Resolver::new()
+        .with_root_servers(vec![address])
+        .with_timeout(Duration::from_millis(100))
+        .with_retries(2);
+
+    let started = Instant::now();
+    let result = resolver.lookup("www.example", Kind::A);
+    let elapsed = started.elapsed();
+    assert!(matches!(result, Err(DnsError::Timeout)));
+    assert_eq!(queries.load(Ordering::SeqCst), 3);
+    // 100 + 200 + 400 ms
+    assert!(elapsed >= Duration::from_millis(700), "{elapsed:?}");
+    assert!(elapsed < Duration::from_secs(3), "{elapsed:?}");
+}
+
+#[test]
+fn queries_are_spread_over_the_servers() {
+    let (port, mut sockets) = bind(&[1, 4]);
+    let second = serve(sockets.pop().unwrap(), root);
+    let first = serve(sockets.pop().unwrap(), root);
+    let resolver = Resolver::new().with_root_servers(vec![
+        SocketAddr::from((Ipv4Addr::new(127, 0, 0, 1), port)),
+        SocketAddr::from((Ipv4Addr::new(127, 0, 0, 4), port)),
+    ]);
+
+    for _ in 0..20 {
+        assert!(matches!(
+            resolver.lookup("nowhere", Kind::A),
+            Err(DnsError::NameError { .. })
+        ));
+    }
+    assert_eq!(
+        first.load(Ordering::SeqCst) + second.load(Ordering::SeqCst),
+        20
+    );
+    assert!(first.load(Ordering::SeqCst) > 0);
+    assert!(second.load(Ordering::SeqCst) > 0);
+}
@@ -0,0 +1,226 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::thread;
+use std::time::Duration;
+
+use weekend_dns::domain_name::DomainName;
+use weekend_dns::error::DnsError;
+use weekend_dns::packet::{Packet, Question, Rcode};
+use weekend_dns::record::{Class, Content, Kind, Record};
+use weekend_dns::resolv_conf::ResolvConf;
+use weekend_dns::stub::StubResolver;
+
+/// Starts a stand-in recursive server on a local port. It knows that
+/// www.example.com is an alias of web.example.com, which has `address`, that
+/// dangling.example.com is an alias of a name that does not exist, and that
+/// nothing else exists. Queries without RD set are refused, and so are
+/// those with an OPT record unless the server knows about `edns`.
+fn stand_in(address: Ipv4Addr, edns: bool) -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let local = socket.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        loop {
+            let (count, source) = socket.recv_from(&mut buf).unwrap();
+            let query = Packet::from_bytes(&buf[..count]).unwrap();
+            let mut response = query.clone();
+            response.edns = None;
+            response.flags.set_response(true);
+            response.flags.set_recursion_available(true);
+
+            let record = |name: &str, data: Content| Record {
+                name: DomainName::new(name),
+                kind: match data {
+                    Content::IPv4(_) => Kind::A,
+                    _ => Kind::CNAME,
+                },
+                class: Class::Internet,
+                ttl: 60,
+                data,
+            };
+            let www = Question::new()
+                .with_domain_name("www.example.com")
+                .with_kind(Kind::A);
+            if query.edns.is_some() && !edns {
+                response.set_rcode(Rcode::FormErr);
+            } else if !query.flags.recursion_desired() {
+                response.set_rcode(Rcode::Refused);
+            } else if query.questions == [www] {
+                response = response
+                    .with_answer(record(
+                        "www.example.com",
+                        Content::DomainName(DomainName::new("web.example.com")),
+                    ))
+                    .with_answer(record("web.example.com", Content::IPv4(address)));
+            } else if query.questions[0].name() == &DomainName::new("dangling.example.com") {
+                response.set_rcode(Rcode::NXDomain);
+                response = response.with_answer(record(
+                    "dangling.example.com",
+                    Content::DomainName(DomainName::new("gone.example.com")),
+                ));
+            } else {
+                response.set_rcode(Rcode::NXDomain);
+            }
+            socket.send_to(&response.to_bytes().unwrap(), source).unwrap();
+        }
+    });
+    local
+}
+
+fn config(nameservers: Vec<SocketAddr>) -> ResolvConf {
+    ResolvConf {
+        nameservers,
+        search: vec![DomainName::new("example.com")],
+        timeout: Duration::from_secs(5),
+        attempts: 1,
+        ..ResolvConf::default()
+    }
+}
+
+#[test]
+fn resolv_conf_is_parsed() {
+    let config = ResolvConf::parse(
+        "# generated by NetworkManager\n\
+         domain corp.example\n\
+         search example.com example.net ; trailing comment\n\
+         nameserver 192.0.2.53\n\
+         nameserver not-an-address\n\
+         nameserver 2001:db8::53\n\
+         nameserver 192.0.2.54\n\
+         nameserver 192.0.2.55\n\
+         options ndots:2 timeout:3 attempts:9 rotate edns0\n\
+         sortlist 130.155.160.0/255.255.240.0\n",
+    );
+    assert_eq!(
+        config.nameservers,
+        [
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 53)), 53),
+            SocketAddr::new(IpAddr::V6("2001:db8::53".parse::<Ipv6Addr>().unwrap()), 53),
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 54)), 53),
+        ]
+    );
+    assert_eq!(
+        config.search,
+        [
+            DomainName::new("example.com"),
+            DomainName::new("example.net")
+        ]
+    );
+    assert_eq!(config.ndots, 2);
+    assert_eq!(config.timeout, Duration::from_secs(3));
+    // capped like the C library does
+    assert_eq!(config.attempts, 5);
+    assert!(config.rotate);
+}
+
+#[test]
+fn empty_resolv_conf_asks_the_local_host() {
+    let config = ResolvConf::parse("search example.com\ndomain corp.example\n");
+    assert_eq!(
+        config.nameservers,
+        [SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 53)]
+    );
+    assert_eq!(config.search, [DomainName::new("corp.example")]);
+    assert_eq!(config.ndots, 1);
+    assert_eq!(config.attempts, 2);
+    assert!(!config.rotate);
+}
+
+#[test]
+fn search_list_order_depends_on_ndots() {
+    let mut config = config(vec![]);
+    config.search.push(DomainName::new("example.net"));
+    let names = |name: &str, config: &ResolvConf| -> Vec<String> {
+        config
+            .candidates(name)
+            .iter()
+            .map(|name| name.to_string())
+            .collect()
+    };
+
+    assert_eq!(
+        names("www", &config),
+        ["www.example.com", "www.example.net", "www"]
+    );
+    assert_eq!(
+        names("www.corp", &config),
+        ["www.corp", "www.corp.example.com", "www.corp.example.net"]
+    );
+    assert_eq!(names("www.corp.", &config), ["www.corp"]);
+    config.ndots = 2;
+    assert_eq!(
+        names("www.corp", &config),
+        ["www.corp.example.com", "www.corp.example.net", "www.corp"]
+    );
+}
+
+#[test]
+fn stub_sends_recursive_queries() {
+    let server = stand_in(Ipv4Addr::new(192, 0, 2, 80), true);
+    let stub = StubResolver::new(config(vec![server]));
+
+    // refused unless RD is set, so any answer shows it was
+    let lookup = stub.lookup("www", Kind::A).unwrap();
+    assert_eq!(lookup.name, DomainName::new("www.example.com"));
+    assert_eq!(lookup.aliases, [DomainName::new("www.example.com")]);
+    assert_eq!(lookup.canonical, DomainName::new("web.example.com"));
+    assert_eq!(
+        lookup.addresses().collect::<Vec<_>>(),
+        [IpAddr::V4(Ipv4Addr::new(192, 0, 2, 80))]
+    );
+    assert_eq!(lookup.server, Some(server.ip()));
+
+    assert!(matches!(
+        stub.lookup("missing", Kind::A),
+        Err(DnsError::NameError { .. })
+    ));
+    assert!(matches!(
+        stub.lookup("dangling.example.com.", Kind::A),
+        Err(DnsError::NameError { name }) if name == DomainName::new("gone.example.com")
+    ));
+}
+
+#[test]
+fn stub_fails_over_to_the_next_server() {
+    let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let server = stand_in(Ipv4Addr::new(192, 0, 2, 80), true);
+    let mut config = config(vec![silent.local_addr().unwrap(), server]);
+    config.timeout = Duration::from_millis(200);
+    let stub = StubResolver::new(config);
+
+    let lookup = stub.lookup("www.example.com.", Kind::A).unwrap();
+    assert_eq!(lookup.records.len(), 1);
+}
+
+#[test]
+fn stub_asks_old_servers_again_without_edns() {
+    let server = stand_in(Ipv4Addr::new(192, 0, 2, 80), false);
+    let stub = StubResolver::new(config(vec![server]));
+    let lookup = stub.lookup("www.example.com.", Kind::A).unwrap();
+    assert_eq!(lookup.records.len(), 1);
+}
+
+#[test]
+fn stub_rotates_between_servers() {
+    let first = stand_in(Ipv4Addr::new(192, 0, 2, 1), true);
+    let second = stand_in(Ipv4Addr::new(192, 0, 2, 2), true);
+    let mut config = config(vec![first, second]);
+    config.rotate = true;
+    let stub = StubResolver::new(config);
+
+    let mut addresses: Vec<IpAddr> = (0..2)
+        .flat_map(|_| {
+            stub.lookup("www.example.com", Kind::A)
+                .unwrap()
+                .addresses()
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    addresses.sort();
+    assert_eq!(
+        addresses,
+        [
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2))
+        ]
+    );
+}
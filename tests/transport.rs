@@ -0,0 +1,224 @@
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, TcpListener, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use weekend_dns::domain_name::DomainName;
+use weekend_dns::error::DnsError;
+use weekend_dns::packet::{Flags, Packet, Question};
+use weekend_dns::record::{Class, Content, Kind, Record};
+use weekend_dns::transport;
+
+#[test]
+fn tcp_messages_are_length_prefixed() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let server = listener.local_addr().unwrap();
+
+    let handle = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut len = [0u8; 2];
+        stream.read_exact(&mut len).unwrap();
+        let mut buf = vec![0u8; u16::from_be_bytes(len) as usize];
+        stream.read_exact(&mut buf).unwrap();
+        let query = Packet::from_bytes(&buf).unwrap();
+
+        let mut flags = Flags::new();
+        flags.set_response(true);
+        let response = Packet::new()
+            .with_id(query.id)
+            .with_flags(flags)
+            .with_question(query.questions[0].clone())
+            .with_answer(Record {
+                name: DomainName::new("example.com"),
+                kind: Kind::A,
+                class: Class::Internet,
+                ttl: 300,
+                data: Content::IPv4(Ipv4Addr::new(192, 0, 2, 1)),
+            })
+            .to_bytes().unwrap();
+        // the length and the message need not arrive together
+        stream
+            .write_all(&(response.len() as u16).to_be_bytes())
+            .unwrap();
+        stream.flush().unwrap();
+        stream.write_all(&response).unwrap();
+        query
+    });
+
+    let query = Packet::new().with_id(0xbeef).with_question(
+        Question::new()
+            .with_domain_name("example.com")
+            .with_kind(Kind::A),
+    );
+    let response = transport::tcp(server, &query, Duration::from_secs(5)).unwrap();
+    assert_eq!(handle.join().unwrap(), query);
+    assert_eq!(response.id, 0xbeef);
+    assert_eq!(
+        response.answers[0].data,
+        Content::IPv4(Ipv4Addr::new(192, 0, 2, 1))
+    );
+}
+
+#[test]
+fn udp_gives_up_after_the_timeout() {
+    // bound but never answered
+    let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let query = Packet::new().with_question(
+        Question::new()
+            .with_domain_name("example.com")
+            .with_kind(Kind::A),
+    );
+    let started = Instant::now();
+    let result = transport::udp(
+        None,
+        silent.local_addr().unwrap(),
+        &query,
+        Duration::from_millis(100),
+    );
+    assert!(matches!(result, Err(DnsError::Timeout)));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn udp_queries_come_from_an_ephemeral_port() {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        let (count, source) = server.recv_from(&mut buf).unwrap();
+        let query = Packet::from_bytes(&buf[..count]).unwrap();
+        let mut flags = Flags::new();
+        flags.set_response(true);
+        let response = Packet::new()
+            .with_id(query.id)
+            .with_flags(flags)
+            .with_question(query.questions[0].clone());
+        server.send_to(&response.to_bytes().unwrap(), source).unwrap();
+        source
+    });
+
+    let query = Packet::new().with_id(7).with_question(
+        Question::new()
+            .with_domain_name("example.com")
+            .with_kind(Kind::A),
+    );
+    let local = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let response = transport::udp(Some(local), addr, &query, Duration::from_secs(5)).unwrap();
+    let source = handle.join().unwrap();
+    assert_eq!(response.id, 7);
+    assert_eq!(source.ip(), local);
+    assert_ne!(source.port(), 5353);
+}
+
+/// Answers the first query arriving on `server` with each of `responses`,
+/// filled in by the closure from the query, in order.
+fn respond(server: UdpSocket, responses: Vec<fn(&Packet) -> Packet>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        let (count, source) = server.recv_from(&mut buf).unwrap();
+        let query = Packet::from_bytes(&buf[..count]).unwrap();
+        for response in responses {
+            server
+                .send_to(&response(&query).to_bytes().unwrap(), source)
+                .unwrap();
+        }
+    })
+}
+
+fn reply(query: &Packet) -> Packet {
+    let mut flags = Flags::new();
+    flags.set_response(true);
+    let mut response = query.clone().with_flags(flags);
+    response.edns = None;
+    response
+}
+
+#[test]
+fn mismatched_responses_are_ignored() {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    let handle = respond(
+        server,
+        vec![
+            |query| reply(query).with_id(query.id.wrapping_add(1)),
+            |query| {
+                let mut response = reply(query);
+                response.flags.set_response(false);
+                response
+            },
+            |query| {
+                let mut response = reply(query);
+                response.questions = vec![Question::new().with_domain_name("example.org")];
+                response
+            },
+            |query| {
+                let mut response = reply(query);
+                response.questions = vec![Question::new()
+                    .with_domain_name("example.com")
+                    .with_kind(Kind::AAAA)];
+                response
+            },
+            |query| {
+                reply(query).with_answer(Record {
+                    name: DomainName::new("example.com"),
+                    kind: Kind::A,
+                    class: Class::Internet,
+                    ttl: 60,
+                    data: Content::IPv4(Ipv4Addr::new(192, 0, 2, 7)),
+                })
+            },
+        ],
+    );
+
+    let query = Packet::new().with_question(
+        Question::new()
+            .with_domain_name("example.com")
+            .with_kind(Kind::A),
+    );
+    let response = transport::udp(None, addr, &query, Duration::from_secs(5)).unwrap();
+    handle.join().unwrap();
+    assert_eq!(response.answers.len(), 1);
+}
+
+#[test]
+fn datagrams_from_other_addresses_are_ignored() {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        let (count, source) = server.recv_from(&mut buf).unwrap();
+        let query = Packet::from_bytes(&buf[..count]).unwrap();
+        let spoofer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        spoofer.send_to(&reply(&query).to_bytes().unwrap(), source).unwrap();
+    });
+
+    let query = Packet::new().with_question(
+        Question::new()
+            .with_domain_name("example.com")
+            .with_kind(Kind::A),
+    );
+    let result = transport::udp(None, addr, &query, Duration::from_millis(300));
+    handle.join().unwrap();
+    assert!(matches!(result, Err(DnsError::Timeout)));
+}
+
+#[test]
+fn case_changes_in_the_question_are_accepted() {
+    let name = DomainName::new("example.com").with_random_case();
+    let lowered = DomainName::new(&name.to_string().to_ascii_lowercase());
+    assert_eq!(name, lowered);
+    assert_eq!(
+        name.eq_exact(&lowered),
+        name.to_string() == lowered.to_string()
+    );
+
+    let query =
+        Packet::new().with_question(Question::new().with_name(DomainName::new("ExAmPlE.cOm")));
+    let mut response = reply(&query);
+    assert!(response.is_response_to(&query));
+    response.questions = vec![Question::new().with_domain_name("example.com")];
+    assert!(response.is_response_to(&query));
+    response.questions = vec![Question::new().with_domain_name("example.org")];
+    assert!(!response.is_response_to(&query));
+}